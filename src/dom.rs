@@ -0,0 +1,622 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// An arena-allocated document tree built from the Hubbub tree callbacks.
+//
+// All the nodes of a document live in a single vector and refer to each other by index, so
+// building the tree needs no reference counting and the whole document is freed at once.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use fragment;
+use hubbub::{Parser, TreeHandler, NodeDataPtr, Tag, Doctype, Attribute, QuirksMode, NoQuirks};
+use hubbub::{ShadowRootInit, HtmlNs};
+
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct NodeId(pub uint);
+
+impl NodeId {
+    // Node handles are offset by one so that no node is ever handed to Hubbub as a null pointer.
+    pub fn from_node_ptr(node: NodeDataPtr) -> NodeId {
+        assert!(node != 0);
+        NodeId(node - 1)
+    }
+
+    pub fn to_node_ptr(&self) -> NodeDataPtr {
+        let NodeId(index) = *self;
+        index + 1
+    }
+}
+
+#[deriving(Clone, Show)]
+pub enum NodeData {
+    DocumentNode,
+    DocumentFragmentNode,
+    ShadowRootNode(ShadowRootInit),
+    DoctypeNode(Doctype),
+    CommentNode(String),
+    TextNode(String),
+    ElementNode(Tag),
+}
+
+#[deriving(Clone, Show)]
+pub struct Node {
+    pub data: NodeData,
+    pub parent: Option<NodeId>,
+    pub prev_sibling: Option<NodeId>,
+    pub next_sibling: Option<NodeId>,
+    pub first_child: Option<NodeId>,
+    pub last_child: Option<NodeId>,
+}
+
+impl Node {
+    fn new(data: NodeData) -> Node {
+        Node {
+            data: data,
+            parent: None,
+            prev_sibling: None,
+            next_sibling: None,
+            first_child: None,
+            last_child: None,
+        }
+    }
+
+    pub fn is_element(&self) -> bool {
+        match self.data {
+            ElementNode(_) => true,
+            _ => false
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        match self.data {
+            TextNode(_) => true,
+            _ => false
+        }
+    }
+}
+
+pub struct Document {
+    pub nodes: Vec<Node>,
+    pub quirks_mode: QuirksMode,
+    pub encoding: Option<String>,
+    // The document fragment holding the contents of each template element.
+    pub template_contents: HashMap<NodeId, NodeId>,
+    // The shadow root attached to each shadow host.
+    pub shadow_roots: HashMap<NodeId, NodeId>,
+    // Slots of removed nodes, which new nodes reuse.
+    pub free_nodes: Vec<NodeId>,
+    // The form the parser associated each form-associated element with.
+    pub form_owners: HashMap<NodeId, NodeId>,
+}
+
+impl Document {
+    pub fn new() -> Document {
+        Document {
+            nodes: vec!(Node::new(DocumentNode)),
+            quirks_mode: NoQuirks,
+            encoding: None,
+            template_contents: HashMap::new(),
+            shadow_roots: HashMap::new(),
+            free_nodes: Vec::new(),
+            form_owners: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn node<'a>(&'a self, id: NodeId) -> &'a Node {
+        let NodeId(index) = id;
+        self.nodes.get(index)
+    }
+
+    pub fn node_mut<'a>(&'a mut self, id: NodeId) -> &'a mut Node {
+        let NodeId(index) = id;
+        self.nodes.get_mut(index)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).first_child
+    }
+
+    pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).last_child
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).next_sibling
+    }
+
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).prev_sibling
+    }
+
+    pub fn tag<'a>(&'a self, id: NodeId) -> Option<&'a Tag> {
+        match self.node(id).data {
+            ElementNode(ref tag) => Some(tag),
+            _ => None
+        }
+    }
+
+    pub fn template_contents(&self, template: NodeId) -> Option<NodeId> {
+        self.template_contents.find(&template).map(|&contents| contents)
+    }
+
+    // Returns the contents of a template element, creating the fragment on first use.
+    pub fn ensure_template_contents(&mut self, template: NodeId) -> NodeId {
+        match self.template_contents(template) {
+            Some(contents) => contents,
+            None => {
+                let contents = self.create_node(DocumentFragmentNode);
+                self.template_contents.insert(template, contents);
+                contents
+            }
+        }
+    }
+
+    pub fn shadow_root(&self, host: NodeId) -> Option<NodeId> {
+        self.shadow_roots.find(&host).map(|&shadow_root| shadow_root)
+    }
+
+    // The mode and flags of the shadow root attached to `host`, if it has one.
+    pub fn shadow_root_init(&self, host: NodeId) -> Option<ShadowRootInit> {
+        self.shadow_root(host).and_then(|shadow_root| {
+            match self.node(shadow_root).data {
+                ShadowRootNode(ref init) => Some(init.clone()),
+                _ => None
+            }
+        })
+    }
+
+    // Attaches a shadow root to `host`, unless it already has one or isn't an element that can
+    // host one.
+    pub fn attach_shadow_root(&mut self, host: NodeId, init: ShadowRootInit) -> Option<NodeId> {
+        let valid_host = match self.tag(host) {
+            Some(tag) => tag.ns == HtmlNs && is_valid_shadow_host_name(tag.name.as_slice()),
+            None => false
+        };
+        if !valid_host || self.shadow_root(host).is_some() {
+            return None
+        }
+        let shadow_root = self.create_node(ShadowRootNode(init));
+        self.shadow_roots.insert(host, shadow_root);
+        Some(shadow_root)
+    }
+
+    pub fn children<'a>(&'a self, id: NodeId) -> Children<'a> {
+        Children {
+            document: self,
+            next: self.first_child(id),
+        }
+    }
+
+    // Iterates over the descendants of `id` in tree order, not including `id` itself.
+    pub fn descendants<'a>(&'a self, id: NodeId) -> Descendants<'a> {
+        Descendants {
+            document: self,
+            root: id,
+            next: self.first_child(id),
+        }
+    }
+
+    pub fn ancestors<'a>(&'a self, id: NodeId) -> Ancestors<'a> {
+        Ancestors {
+            document: self,
+            next: self.parent(id),
+        }
+    }
+
+    // The concatenated data of all the text nodes under `id`.
+    pub fn text_content(&self, id: NodeId) -> String {
+        let mut result = String::new();
+        for descendant in self.descendants(id) {
+            match self.node(descendant).data {
+                TextNode(ref text) => result.push_str(text.as_slice()),
+                _ => {}
+            }
+        }
+        result
+    }
+
+    pub fn create_node(&mut self, data: NodeData) -> NodeId {
+        match self.free_nodes.pop() {
+            Some(id) => {
+                *self.node_mut(id) = Node::new(data);
+                id
+            }
+            None => {
+                self.nodes.push(Node::new(data));
+                NodeId(self.nodes.len() - 1)
+            }
+        }
+    }
+
+    // Detaches `node` and frees it along with its descendants, template contents and shadow
+    // roots. Their ids are reused for new nodes, so nothing may refer to them any more.
+    pub fn remove(&mut self, node: NodeId) {
+        self.detach(node);
+        let mut removed = HashSet::new();
+        let mut stack = vec!(node);
+        loop {
+            let id = match stack.pop() {
+                Some(id) => id,
+                None => break
+            };
+            stack.extend(self.children(id));
+            match self.template_contents.pop(&id) {
+                Some(contents) => stack.push(contents),
+                None => {}
+            }
+            match self.shadow_roots.pop(&id) {
+                Some(shadow_root) => stack.push(shadow_root),
+                None => {}
+            }
+            self.form_owners.pop(&id);
+            *self.node_mut(id) = Node::new(CommentNode(String::new()));
+            self.free_nodes.push(id);
+            removed.insert(id);
+        }
+        // Controls left in the document lose a removed form owner too.
+        let orphans: Vec<NodeId> = self.form_owners.iter().filter(|&(_, form)| {
+            removed.contains(form)
+        }).map(|(&control, _)| control).collect();
+        for control in orphans.iter() {
+            self.form_owners.pop(control);
+        }
+    }
+
+    // Appends `child` as the last child of `parent`, returning the node that was actually
+    // inserted. Text appended directly after another text node is merged into it.
+    pub fn append(&mut self, parent: NodeId, child: NodeId) -> NodeId {
+        self.detach(child);
+        match self.last_child(parent) {
+            Some(last) if self.merge_text(last, child) => return last,
+            _ => {}
+        }
+
+        let last = self.last_child(parent);
+        {
+            let node = self.node_mut(child);
+            node.parent = Some(parent);
+            node.prev_sibling = last;
+        }
+        match last {
+            Some(last) => self.node_mut(last).next_sibling = Some(child),
+            None => self.node_mut(parent).first_child = Some(child),
+        }
+        self.node_mut(parent).last_child = Some(child);
+        child
+    }
+
+    // Inserts `child` immediately before `reference`, which must be a child of `parent`.
+    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, reference: NodeId) -> NodeId {
+        assert!(self.parent(reference) == Some(parent));
+        self.detach(child);
+        let prev = self.prev_sibling(reference);
+        match prev {
+            Some(prev) if self.merge_text(prev, child) => return prev,
+            _ => {}
+        }
+
+        {
+            let node = self.node_mut(child);
+            node.parent = Some(parent);
+            node.prev_sibling = prev;
+            node.next_sibling = Some(reference);
+        }
+        self.node_mut(reference).prev_sibling = Some(child);
+        match prev {
+            Some(prev) => self.node_mut(prev).next_sibling = Some(child),
+            None => self.node_mut(parent).first_child = Some(child),
+        }
+        child
+    }
+
+    // Removes `child` from its parent, if it has one. The node stays in the arena.
+    pub fn detach(&mut self, child: NodeId) {
+        let (parent, prev, next) = {
+            let node = self.node_mut(child);
+            let links = (node.parent, node.prev_sibling, node.next_sibling);
+            node.parent = None;
+            node.prev_sibling = None;
+            node.next_sibling = None;
+            links
+        };
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return
+        };
+        match prev {
+            Some(prev) => self.node_mut(prev).next_sibling = next,
+            None => self.node_mut(parent).first_child = next,
+        }
+        match next {
+            Some(next) => self.node_mut(next).prev_sibling = prev,
+            None => self.node_mut(parent).last_child = prev,
+        }
+    }
+
+    pub fn reparent_children(&mut self, node: NodeId, new_parent: NodeId) {
+        loop {
+            match self.first_child(node) {
+                Some(child) => { self.append(new_parent, child); }
+                None => break
+            }
+        }
+    }
+
+    pub fn clone_node(&mut self, node: NodeId, deep: bool) -> NodeId {
+        let data = self.node(node).data.clone();
+        let clone = self.create_node(data);
+        if deep {
+            self.clone_children(node, clone);
+            match self.template_contents(node) {
+                Some(contents) => {
+                    let clone_contents = self.ensure_template_contents(clone);
+                    self.clone_children(contents, clone_contents);
+                }
+                None => {}
+            }
+        }
+        // A clonable shadow root is cloned along with its host, even by a shallow clone.
+        match self.shadow_root_init(node) {
+            Some(init) if init.clonable => {
+                let shadow_root = self.shadow_root(node).unwrap();
+                let clone_shadow_root = self.attach_shadow_root(clone, init).unwrap();
+                self.clone_children(shadow_root, clone_shadow_root);
+            }
+            _ => {}
+        }
+        clone
+    }
+
+    fn clone_children(&mut self, node: NodeId, clone: NodeId) {
+        let children: Vec<NodeId> = self.children(node).collect();
+        for child in children.move_iter() {
+            let child_clone = self.clone_node(child, true);
+            self.append(clone, child_clone);
+        }
+    }
+
+    // Adds the attributes that the element doesn't already have, as the parser does when it sees
+    // a second `<html>` or `<body>` start tag.
+    pub fn add_attributes(&mut self, node: NodeId, attributes: Vec<Attribute>) {
+        match self.node_mut(node).data {
+            ElementNode(ref mut tag) => {
+                for attribute in attributes.move_iter() {
+                    let present = tag.attributes.iter().any(|existing| {
+                        existing.ns == attribute.ns && existing.name == attribute.name
+                    });
+                    if !present {
+                        tag.attributes.push(attribute);
+                    }
+                }
+            }
+            _ => fail!("adding attributes to a non-element node")
+        }
+    }
+
+    fn merge_text(&mut self, existing: NodeId, new: NodeId) -> bool {
+        let text = match self.node(new).data {
+            TextNode(ref text) => text.clone(),
+            _ => return false
+        };
+        match self.node_mut(existing).data {
+            TextNode(ref mut existing_text) => {
+                existing_text.push_str(text.as_slice());
+                true
+            }
+            _ => false
+        }
+    }
+}
+
+fn is_valid_shadow_host_name(name: &str) -> bool {
+    match name {
+        "article" | "aside" | "blockquote" | "body" | "div" | "footer" | "h1" | "h2" | "h3" |
+        "h4" | "h5" | "h6" | "header" | "main" | "nav" | "p" | "section" | "span" => true,
+        // Autonomous custom elements.
+        _ => name.contains_char('-')
+    }
+}
+
+pub struct Children<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator<NodeId> for Children<'a> {
+    fn next(&mut self) -> Option<NodeId> {
+        let current = self.next;
+        match current {
+            Some(id) => self.next = self.document.next_sibling(id),
+            None => {}
+        }
+        current
+    }
+}
+
+pub struct Descendants<'a> {
+    document: &'a Document,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator<NodeId> for Descendants<'a> {
+    fn next(&mut self) -> Option<NodeId> {
+        let current = match self.next {
+            Some(id) => id,
+            None => return None
+        };
+
+        self.next = match self.document.first_child(current) {
+            Some(child) => Some(child),
+            None => self.next_after_subtree(current)
+        };
+        Some(current)
+    }
+}
+
+impl<'a> Descendants<'a> {
+    // The next node in tree order that is not a descendant of `node`, staying within the root.
+    fn next_after_subtree(&self, node: NodeId) -> Option<NodeId> {
+        let mut node = node;
+        while node != self.root {
+            match self.document.next_sibling(node) {
+                Some(sibling) => return Some(sibling),
+                None => {}
+            }
+            match self.document.parent(node) {
+                Some(parent) => node = parent,
+                None => return None
+            }
+        }
+        None
+    }
+}
+
+pub struct Ancestors<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator<NodeId> for Ancestors<'a> {
+    fn next(&mut self) -> Option<NodeId> {
+        let current = self.next;
+        match current {
+            Some(id) => self.next = self.document.parent(id),
+            None => {}
+        }
+        current
+    }
+}
+
+// Builds a tree handler that constructs the tree into `document` and hands it to `f`. Reference
+// counting requests from Hubbub are ignored: nodes live exactly as long as the document.
+pub fn with_tree_handler<R>(document: &RefCell<Document>, f: |&mut TreeHandler| -> R) -> R {
+    let mut tree_handler = TreeHandler {
+        create_comment: |data: String| {
+            document.borrow_mut().create_node(CommentNode(data)).to_node_ptr()
+        },
+        create_doctype: |doctype: Box<Doctype>| {
+            document.borrow_mut().create_node(DoctypeNode(*doctype)).to_node_ptr()
+        },
+        create_element: |tag: Box<Tag>| {
+            document.borrow_mut().create_node(ElementNode(*tag)).to_node_ptr()
+        },
+        create_text: |data: String| {
+            document.borrow_mut().create_node(TextNode(data)).to_node_ptr()
+        },
+        ref_node: |_| {},
+        unref_node: |_| {},
+        append_child: |parent, child| {
+            let mut doc = document.borrow_mut();
+            doc.append(NodeId::from_node_ptr(parent), NodeId::from_node_ptr(child)).to_node_ptr()
+        },
+        insert_before: |parent, child, reference| {
+            let mut doc = document.borrow_mut();
+            doc.insert_before(NodeId::from_node_ptr(parent),
+                              NodeId::from_node_ptr(child),
+                              NodeId::from_node_ptr(reference)).to_node_ptr()
+        },
+        remove_child: |_, child| {
+            document.borrow_mut().detach(NodeId::from_node_ptr(child));
+            child
+        },
+        clone_node: |node, deep| {
+            document.borrow_mut().clone_node(NodeId::from_node_ptr(node), deep).to_node_ptr()
+        },
+        reparent_children: |node, new_parent| {
+            document.borrow_mut().reparent_children(NodeId::from_node_ptr(node),
+                                                    NodeId::from_node_ptr(new_parent));
+            new_parent
+        },
+        get_parent: |node, element_only| {
+            let doc = document.borrow();
+            match doc.parent(NodeId::from_node_ptr(node)) {
+                Some(parent) if !element_only || doc.node(parent).is_element() => {
+                    parent.to_node_ptr()
+                }
+                _ => 0
+            }
+        },
+        has_children: |node| {
+            document.borrow().first_child(NodeId::from_node_ptr(node)).is_some()
+        },
+        form_associate: |form, node| {
+            let mut doc = document.borrow_mut();
+            doc.form_owners.insert(NodeId::from_node_ptr(node), NodeId::from_node_ptr(form));
+        },
+        add_attributes: |node, attributes| {
+            document.borrow_mut().add_attributes(NodeId::from_node_ptr(node), attributes)
+        },
+        set_quirks_mode: |mode| {
+            document.borrow_mut().quirks_mode = mode
+        },
+        encoding_change: |encname| {
+            document.borrow_mut().encoding = Some(encname)
+        },
+        complete_script: |_| {},
+        complete_style: |_| {},
+        get_template_contents: |template| {
+            let mut doc = document.borrow_mut();
+            doc.ensure_template_contents(NodeId::from_node_ptr(template)).to_node_ptr()
+        },
+        attach_shadow_root: |host, init| {
+            let mut doc = document.borrow_mut();
+            match doc.attach_shadow_root(NodeId::from_node_ptr(host), init) {
+                Some(shadow_root) => shadow_root.to_node_ptr(),
+                None => 0
+            }
+        },
+        element_finished: |_| {},
+    };
+    f(&mut tree_handler)
+}
+
+// Parses a complete document into an arena.
+pub fn parse(data: &[u8], encoding: &str) -> Document {
+    let document = RefCell::new(Document::new());
+    with_tree_handler(&document, |tree_handler| {
+        let root = document.borrow().root().to_node_ptr();
+        let mut parser = Parser::new(encoding, false);
+        parser.set_tree_handler(tree_handler);
+        parser.set_document_node(root);
+        parser.parse_chunk(data);
+        parser.completed();
+    });
+    document.unwrap()
+}
+
+// Parses `data` as the contents of an element like `context`. The top-level nodes of the
+// fragment become the children of the returned document's root.
+pub fn parse_fragment(context: &Tag, data: &[u8]) -> Document {
+    let document = RefCell::new(Document::new());
+    let nodes = with_tree_handler(&document, |tree_handler| {
+        let root = document.borrow().root().to_node_ptr();
+        fragment::parse_fragment(context, data, root, tree_handler)
+    });
+
+    let mut document = document.unwrap();
+    let root = document.root();
+    let children: Vec<NodeId> = document.children(root).collect();
+    for &child in children.iter() {
+        document.detach(child);
+    }
+    for &node in nodes.iter() {
+        document.append(root, NodeId::from_node_ptr(node));
+    }
+    document
+}
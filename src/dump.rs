@@ -0,0 +1,135 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Dumps arena documents in the "#document" format of the html5lib tree construction tests.
+
+use std::io::{IoResult, MemWriter, Writer};
+use dom::{Document, NodeId, DocumentNode, DocumentFragmentNode, DoctypeNode, CommentNode, TextNode};
+use dom::{ElementNode, ShadowRootNode};
+use hubbub::{Ns, NullNs, HtmlNs, MathMlNs, SvgNs, XLinkNs, XmlNs, XmlNsNs};
+use hubbub::{OpenShadowRoot, ClosedShadowRoot};
+
+// Writes the children of `node`, one line each, indented by their depth below it.
+pub fn dump(writer: &mut Writer, document: &Document, node: NodeId) -> IoResult<()> {
+    for child in document.children(node) {
+        try!(dump_node(writer, document, child, 0));
+    }
+    Ok(())
+}
+
+pub fn to_string(document: &Document, node: NodeId) -> String {
+    let mut writer = MemWriter::new();
+    dump(&mut writer, document, node).unwrap();
+    String::from_utf8(writer.unwrap()).unwrap()
+}
+
+fn dump_node(writer: &mut Writer, document: &Document, node: NodeId, depth: uint)
+             -> IoResult<()> {
+    try!(write_indent(writer, depth));
+    match document.node(node).data {
+        DocumentNode | DocumentFragmentNode | ShadowRootNode(_) => {
+            fail!("document node below the root")
+        }
+        DoctypeNode(ref doctype) => {
+            try!(write!(writer, "<!DOCTYPE {}", doctype.name));
+            if doctype.public_id.is_some() || doctype.system_id.is_some() {
+                try!(write!(writer, " \"{}\" \"{}\"",
+                            doctype.public_id.as_ref().map_or("", |id| id.as_slice()),
+                            doctype.system_id.as_ref().map_or("", |id| id.as_slice())));
+            }
+            try!(writer.write_str(">\n"));
+        }
+        CommentNode(ref data) => try!(write!(writer, "<!-- {} -->\n", data)),
+        TextNode(ref data) => try!(write!(writer, "\"{}\"\n", data)),
+        ElementNode(ref tag) => {
+            match element_prefix(tag.ns) {
+                Some(prefix) => try!(write!(writer, "<{} {}>\n", prefix, tag.name)),
+                None => try!(write!(writer, "<{}>\n", tag.name)),
+            }
+
+            let mut attributes: Vec<String> = tag.attributes.iter().map(|attribute| {
+                match attribute_prefix(attribute.ns) {
+                    Some(prefix) => format!("{} {}=\"{}\"", prefix, attribute.name, attribute.value),
+                    None => format!("{}=\"{}\"", attribute.name, attribute.value),
+                }
+            }).collect();
+            attributes.as_mut_slice().sort();
+            for attribute in attributes.iter() {
+                try!(write_indent(writer, depth + 1));
+                try!(writer.write_str(attribute.as_slice()));
+                try!(writer.write_str("\n"));
+            }
+        }
+    }
+
+    // A shadow root is shown before the children of its host, as "#shadow-root" followed by its
+    // mode and flags.
+    match document.shadow_root_init(node) {
+        Some(init) => {
+            try!(write_indent(writer, depth + 1));
+            try!(writer.write_str(match init.mode {
+                OpenShadowRoot => "#shadow-root (open",
+                ClosedShadowRoot => "#shadow-root (closed",
+            }));
+            if init.delegates_focus {
+                try!(writer.write_str(", delegatesfocus"));
+            }
+            if init.clonable {
+                try!(writer.write_str(", clonable"));
+            }
+            try!(writer.write_str(")\n"));
+            for child in document.children(document.shadow_root(node).unwrap()) {
+                try!(dump_node(writer, document, child, depth + 2));
+            }
+        }
+        None => {}
+    }
+
+    for child in document.children(node) {
+        try!(dump_node(writer, document, child, depth + 1));
+    }
+
+    // Template contents are shown as a "content" child of the template element.
+    match document.template_contents(node) {
+        Some(contents) => {
+            try!(write_indent(writer, depth + 1));
+            try!(writer.write_str("content\n"));
+            for child in document.children(contents) {
+                try!(dump_node(writer, document, child, depth + 2));
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+fn write_indent(writer: &mut Writer, depth: uint) -> IoResult<()> {
+    try!(writer.write_str("| "));
+    for _ in range(0, depth) {
+        try!(writer.write_str("  "));
+    }
+    Ok(())
+}
+
+fn element_prefix(ns: Ns) -> Option<&'static str> {
+    match ns {
+        SvgNs => Some("svg"),
+        MathMlNs => Some("math"),
+        NullNs | HtmlNs | XLinkNs | XmlNs | XmlNsNs => None,
+    }
+}
+
+fn attribute_prefix(ns: Ns) -> Option<&'static str> {
+    match ns {
+        XLinkNs => Some("xlink"),
+        XmlNs => Some("xml"),
+        XmlNsNs => Some("xmlns"),
+        NullNs | HtmlNs | MathMlNs | SvgNs => None,
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The forms of a parsed document and the controls they own, as a browser would submit them.
+
+use std::ascii::StrAsciiExt;
+use std::collections::HashMap;
+use dom::{Document, NodeId};
+use hubbub::{Tag, HtmlNs, is_html_whitespace};
+use url;
+
+// The elements that can have a form owner.
+static LISTED_ELEMENTS: &'static [&'static str] = &[
+    "button", "fieldset", "input", "object", "output", "select", "textarea",
+];
+
+static INPUT_TYPES: &'static [&'static str] = &[
+    "button", "checkbox", "color", "date", "datetime-local", "email", "file", "hidden", "image",
+    "month", "number", "password", "radio", "range", "reset", "search", "submit", "tel", "text",
+    "time", "url", "week",
+];
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct SelectOption {
+    pub value: String,
+    pub label: String,
+    pub selected: bool,
+    // Whether the option or its option group is disabled.
+    pub disabled: bool,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Control {
+    pub node: NodeId,
+    pub element: String,
+    pub name: Option<String>,
+    // The input type for inputs, "select-one" or "select-multiple" for selects, and the
+    // element name for the others.
+    pub control_type: String,
+    // The value the control starts with: the value attribute, the text of a textarea, or the
+    // value of the first selected option.
+    pub default_value: Option<String>,
+    // Whether a checkbox or radio button starts checked.
+    pub checked: bool,
+    pub options: Vec<SelectOption>,
+    pub required: bool,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Form {
+    pub node: NodeId,
+    pub name: Option<String>,
+    // The action resolved against the base URL of the document.
+    pub action: String,
+    // "get", "post" or "dialog".
+    pub method: String,
+    pub enctype: String,
+    pub controls: Vec<Control>,
+}
+
+// Lists the forms of a document in tree order, each with the controls it owns.
+pub fn forms(document: &Document, document_url: &str) -> Vec<Form> {
+    let base_url = url::document_base_url(document, document_url);
+    let mut forms = Vec::new();
+    for node in document.descendants(document.root()) {
+        let tag = match document.tag(node) {
+            Some(tag) if tag.ns == HtmlNs && tag.name.as_slice() == "form" => tag,
+            _ => continue
+        };
+        let action = match tag.get_attribute("action") {
+            Some(action) if !action.trim_chars(is_html_whitespace).is_empty() => {
+                url::resolve(base_url.as_slice(), action).unwrap_or(url::clean_url(action))
+            }
+            _ => document_url.to_string()
+        };
+        let method = keyword(tag, "method", &["get", "post", "dialog"], "get");
+        let enctype = keyword(tag, "enctype", &["application/x-www-form-urlencoded",
+                                                 "multipart/form-data", "text/plain"],
+                              "application/x-www-form-urlencoded");
+        forms.push(Form {
+            node: node,
+            name: tag.get_attribute("name").map(|name| name.to_string()),
+            action: action,
+            method: method,
+            enctype: enctype,
+            controls: Vec::new(),
+        });
+    }
+
+    let ids = element_ids(document);
+    for node in document.descendants(document.root()) {
+        let owner = match owner(document, node, &ids) {
+            Some(owner) => owner,
+            None => continue
+        };
+        match forms.mut_iter().find(|form| form.node == owner) {
+            Some(form) => form.controls.push(control(document, node)),
+            None => {}
+        }
+    }
+    forms
+}
+
+// The form that owns `node`: the form its form attribute names if it has one, or else the form
+// the parser associated it with, or else its nearest ancestor form.
+pub fn form_owner(document: &Document, node: NodeId) -> Option<NodeId> {
+    owner(document, node, &element_ids(document))
+}
+
+// The first element with each ID, in tree order.
+fn element_ids(document: &Document) -> HashMap<String, NodeId> {
+    let mut ids = HashMap::new();
+    for node in document.descendants(document.root()) {
+        match document.tag(node).and_then(|tag| tag.get_attribute("id")) {
+            Some(id) if !id.is_empty() && !ids.contains_key(&id.to_string()) => {
+                ids.insert(id.to_string(), node);
+            }
+            _ => {}
+        }
+    }
+    ids
+}
+
+fn owner(document: &Document, node: NodeId, ids: &HashMap<String, NodeId>) -> Option<NodeId> {
+    let tag = match document.tag(node) {
+        Some(tag) if is_listed_element(tag) => tag,
+        _ => return None
+    };
+    match tag.get_attribute("form") {
+        // Only the first element with the ID counts, even if it isn't a form. An empty form
+        // attribute names no element.
+        Some(id) => {
+            return match ids.find(&id.to_string()) {
+                Some(&other) if is_form(document, other) => Some(other),
+                _ => None
+            }
+        }
+        None => {}
+    }
+    match document.form_owners.find(&node) {
+        Some(&form) if is_form(document, form) => return Some(form),
+        _ => {}
+    }
+    document.ancestors(node).find(|&ancestor| is_form(document, ancestor))
+}
+
+fn is_form(document: &Document, node: NodeId) -> bool {
+    document.tag(node).map_or(false, |tag| tag.ns == HtmlNs && tag.name.as_slice() == "form")
+}
+
+fn is_listed_element(tag: &Tag) -> bool {
+    tag.ns == HtmlNs && LISTED_ELEMENTS.iter().any(|name| tag.name.as_slice() == *name)
+}
+
+// The value of an enumerated attribute, lowercased, or `default` if it is missing or invalid.
+fn keyword(tag: &Tag, name: &str, keywords: &[&str], default: &str) -> String {
+    let value = tag.get_attribute(name).map(|value| {
+        value.trim_chars(is_html_whitespace).to_ascii_lower()
+    });
+    match value {
+        Some(value) if keywords.iter().any(|keyword| value.as_slice() == *keyword) => value,
+        _ => default.to_string()
+    }
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    let words: Vec<&str> = value.split(is_html_whitespace).filter(|word| {
+        !word.is_empty()
+    }).collect();
+    words.connect(" ")
+}
+
+fn control(document: &Document, node: NodeId) -> Control {
+    let tag = document.tag(node).unwrap();
+    let attribute = |name: &str| tag.get_attribute(name).map(|value| value.to_string());
+    let mut control = Control {
+        node: node,
+        element: tag.name.clone(),
+        name: attribute("name"),
+        control_type: tag.name.clone(),
+        default_value: None,
+        checked: false,
+        options: Vec::new(),
+        required: tag.has_attribute("required"),
+    };
+    match tag.name.as_slice() {
+        "input" => {
+            control.control_type = keyword(tag, "type", INPUT_TYPES, "text");
+            let checkable = control.control_type.as_slice() == "checkbox" ||
+                            control.control_type.as_slice() == "radio";
+            control.checked = checkable && tag.has_attribute("checked");
+            control.default_value = match attribute("value") {
+                Some(value) => Some(value),
+                None if checkable => Some("on".to_string()),
+                None => None
+            };
+        }
+        "button" => {
+            control.control_type = keyword(tag, "type", &["button", "reset", "submit"], "submit");
+            control.default_value = attribute("value");
+        }
+        "textarea" => {
+            control.control_type = "textarea".to_string();
+            control.default_value = Some(document.text_content(node));
+        }
+        "output" => {
+            control.default_value = Some(document.text_content(node));
+        }
+        "select" => {
+            let multiple = tag.has_attribute("multiple");
+            let control_type = if multiple { "select-multiple" } else { "select-one" };
+            control.control_type = control_type.to_string();
+            control.options = select_options(document, node);
+            if !multiple {
+                // A drop-down list selects only the last of its selected options, or else its
+                // first option that isn't disabled.
+                let selected = match control.options.iter().rposition(|option| option.selected) {
+                    Some(index) => Some(index),
+                    None => control.options.iter().position(|option| !option.disabled)
+                };
+                for (index, option) in control.options.mut_iter().enumerate() {
+                    option.selected = selected == Some(index);
+                }
+            }
+            control.default_value = control.options.iter().find(|option| option.selected)
+                                                   .map(|option| option.value.clone());
+        }
+        _ => {}
+    }
+    control
+}
+
+// The options of a select element, including those in option groups.
+fn select_options(document: &Document, select: NodeId) -> Vec<SelectOption> {
+    let mut options = Vec::new();
+    for child in document.children(select) {
+        let tag = match document.tag(child) {
+            Some(tag) if tag.ns == HtmlNs => tag,
+            _ => continue
+        };
+        match tag.name.as_slice() {
+            "option" => options.push(select_option(document, child, tag, false)),
+            "optgroup" => {
+                let disabled = tag.has_attribute("disabled");
+                for option in document.children(child) {
+                    match document.tag(option) {
+                        Some(tag) if tag.ns == HtmlNs && tag.name.as_slice() == "option" => {
+                            options.push(select_option(document, option, tag, disabled));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    options
+}
+
+fn select_option(document: &Document, node: NodeId, tag: &Tag, group_disabled: bool)
+                 -> SelectOption {
+    let text = collapse_whitespace(document.text_content(node).as_slice());
+    SelectOption {
+        value: tag.get_attribute("value").map_or(text.clone(), |value| value.to_string()),
+        label: match tag.get_attribute("label") {
+            Some(label) if !label.is_empty() => label.to_string(),
+            _ => text
+        },
+        selected: tag.has_attribute("selected"),
+        disabled: group_disabled || tag.has_attribute("disabled"),
+    }
+}
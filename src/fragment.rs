@@ -0,0 +1,182 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Fragment parsing with a context element, as for `element.innerHTML = ...`.
+//
+// Hubbub has no fragment parsing mode, so the context is recreated instead: a prologue of start
+// tags that opens an element like the context one is parsed first, which leaves the tree builder
+// in the insertion mode the context implies, and the tokenizer is then switched to the context's
+// content model. The nodes that end up as children of that element are the fragment, along with
+// any content foster parented out of the table that a table context stands in or is in.
+
+use std::cell::RefCell;
+use hubbub::{Parser, TreeHandler, TreeObserver, NodeDataPtr, Tag, ContentModel, RCData, CData};
+use hubbub::{PlainText, HtmlNs, SvgNs, MathMlNs, observe_tree};
+
+struct FragmentState {
+    last_element: NodeDataPtr,
+    creating_table: bool,
+    // The last table opened by the prologue, which foster parented content goes before.
+    table: NodeDataPtr,
+    // The template standing in for a template context, whose contents become the context.
+    template: NodeDataPtr,
+    context: NodeDataPtr,
+    children: Vec<NodeDataPtr>,
+}
+
+impl TreeObserver for FragmentState {
+    fn creating_element(&mut self, tag: &Tag) {
+        self.creating_table = tag.is_html_element_in(&["table"]);
+    }
+
+    fn element_created(&mut self, element: NodeDataPtr) {
+        self.last_element = element;
+        if self.creating_table && self.context == 0 && self.template == 0 {
+            self.table = element;
+        }
+    }
+
+    fn template_contents_used(&mut self, template: NodeDataPtr, contents: NodeDataPtr) {
+        if self.template != 0 && template == self.template {
+            self.context = contents;
+        }
+    }
+
+    fn child_appended(&mut self, parent: NodeDataPtr, node: NodeDataPtr) {
+        if self.context != 0 && parent == self.context && !self.children.contains(&node) {
+            self.children.push(node);
+        }
+    }
+
+    fn child_inserted(&mut self, parent: NodeDataPtr, node: NodeDataPtr, reference: NodeDataPtr) {
+        if self.context == 0 || self.children.contains(&node) {
+            return
+        }
+        if parent == self.context {
+            let index = self.children.iter().position(|&child| child == reference);
+            self.children.insert(index.unwrap_or(self.children.len()), node);
+        } else if self.table != 0 && reference == self.table {
+            // With a table context there is no table to foster parent content out of, so it
+            // goes to the root of the fragment after what is there already.
+            self.children.push(node);
+        }
+    }
+
+    // A node is only ever in the fragment as the child of one parent, so it leaves the fragment
+    // whichever parent it is removed from.
+    fn child_removed(&mut self, _parent: NodeDataPtr, node: NodeDataPtr) {
+        self.children.retain(|&child| child != node);
+    }
+
+    // Children moved into the context element by the adoption agency algorithm aren't seen
+    // here, since the tree handler interface has no way to enumerate them.
+    fn children_reparented(&mut self, node: NodeDataPtr, _new_parent: NodeDataPtr) {
+        if self.context != 0 && node == self.context {
+            self.children.clear();
+        }
+    }
+}
+
+// The markup that opens an element standing in for `context`, and the content model its
+// contents are tokenized in.
+fn prologue(context: &Tag) -> (String, Option<ContentModel>) {
+    let name = context.name.as_slice();
+    let mut prologue = "<!DOCTYPE html>".to_string();
+    let content_model = match context.ns {
+        SvgNs if name != "svg" => {
+            prologue.push_str(format!("<body><svg><{}>", name).as_slice());
+            None
+        }
+        MathMlNs if name != "math" => {
+            prologue.push_str(format!("<body><math><{}>", name).as_slice());
+            None
+        }
+        SvgNs | MathMlNs => {
+            prologue.push_str(format!("<body><{}>", name).as_slice());
+            None
+        }
+        HtmlNs => {
+            let (markup, content_model) = match name {
+                "html" => ("<html>", None),
+                "frameset" => ("<frameset>", None),
+                "table" => ("<table>", None),
+                "caption" => ("<table><caption>", None),
+                "colgroup" => ("<table><colgroup>", None),
+                "tbody" => ("<table><tbody>", None),
+                "thead" => ("<table><thead>", None),
+                "tfoot" => ("<table><tfoot>", None),
+                "tr" => ("<table><tbody><tr>", None),
+                "td" => ("<table><tbody><tr><td>", None),
+                "th" => ("<table><tbody><tr><th>", None),
+                "select" => ("<body><select>", None),
+                "template" => ("<body><template>", None),
+                "title" => ("<body><title>", Some(RCData)),
+                "textarea" => ("<body><textarea>", Some(RCData)),
+                "style" => ("<body><style>", Some(CData)),
+                "script" => ("<body><script>", Some(CData)),
+                "xmp" => ("<body><xmp>", Some(CData)),
+                "iframe" => ("<body><iframe>", Some(CData)),
+                "noembed" => ("<body><noembed>", Some(CData)),
+                "noframes" => ("<body><noframes>", Some(CData)),
+                "plaintext" => ("<body><plaintext>", Some(PlainText)),
+                // Any other context puts the tree builder "in body" without the context element
+                // itself being open, so the body element stands in for it.
+                _ => ("<body>", None),
+            };
+            prologue.push_str(markup);
+            content_model
+        }
+        _ => {
+            prologue.push_str("<body>");
+            None
+        }
+    };
+    (prologue, content_model)
+}
+
+// Parses `data` as the contents of an element like `context`, building nodes with
+// `tree_handler` under `document`. Returns the top-level nodes of the fragment, which are
+// left as the children of the element standing in for the context, or before its table.
+pub fn parse_fragment(context: &Tag, data: &[u8], document: NodeDataPtr,
+                      tree_handler: &mut TreeHandler) -> Vec<NodeDataPtr> {
+    let (prologue, content_model) = prologue(context);
+    let state = RefCell::new(FragmentState {
+        last_element: 0,
+        creating_table: false,
+        table: 0,
+        template: 0,
+        context: 0,
+        children: Vec::new(),
+    });
+
+    observe_tree(tree_handler, &state, |fragment_handler| {
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_tree_handler(fragment_handler);
+        parser.set_document_node(document);
+        parser.parse_chunk(prologue.as_bytes());
+        {
+            // Children inserted into a template go into its contents instead, which the tree
+            // handler is asked for on the first insertion.
+            let mut state = state.borrow_mut();
+            if context.ns == HtmlNs && context.name.as_slice() == "template" {
+                state.template = state.last_element;
+            } else {
+                state.context = state.last_element;
+            }
+        }
+        match content_model {
+            Some(content_model) => parser.set_content_model(content_model),
+            None => {}
+        }
+        parser.parse_chunk(data);
+        parser.completed();
+    });
+
+    state.unwrap().children
+}
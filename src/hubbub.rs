@@ -11,16 +11,39 @@
 
 use libc;
 use libc::{c_void, size_t};
+use std::ascii::StrAsciiExt;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ptr;
+use std::string;
 use ll;
+use url;
 
+#[deriving(Clone, PartialEq, Show)]
 pub enum QuirksMode {
     NoQuirks,
     LimitedQuirks,
     FullQuirks
 }
 
+#[deriving(Clone, PartialEq, Show)]
+pub enum ContentModel {
+    PCData,
+    RCData,
+    CData,
+    PlainText
+}
+
+// How the charset of the input was determined, from least to most certain.
+#[deriving(Clone, PartialEq, Show)]
+pub enum CharsetSource {
+    CharsetUnknown,
+    CharsetTentative,
+    CharsetConfident
+}
+
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
 pub enum Ns {
     NullNs,
     HtmlNs,
@@ -31,6 +54,7 @@ pub enum Ns {
     XmlNsNs
 }
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct Doctype {
     pub name: String,
     pub public_id: Option<String>,
@@ -38,12 +62,14 @@ pub struct Doctype {
     pub force_quirks: bool
 }
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct Attribute {
     pub ns: Ns,
     pub name: String,
     pub value: String,
 }
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct Tag {
     pub ns: Ns,
     pub name: String,
@@ -51,6 +77,54 @@ pub struct Tag {
     pub self_closing: bool
 }
 
+#[deriving(Clone, PartialEq, Show)]
+pub enum ShadowRootMode {
+    OpenShadowRoot,
+    ClosedShadowRoot
+}
+
+// The shadow root requested by a `<template shadowrootmode>` element.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ShadowRootInit {
+    pub mode: ShadowRootMode,
+    pub delegates_focus: bool,
+    pub clonable: bool
+}
+
+impl Tag {
+    pub fn get_attribute<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.attributes.iter().find(|attribute| {
+            attribute.ns == NullNs && attribute.name.as_slice() == name
+        }).map(|attribute| attribute.value.as_slice())
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    // Whether this is an HTML element with one of `names`.
+    pub fn is_html_element_in(&self, names: &[&str]) -> bool {
+        self.ns == HtmlNs && names.iter().any(|name| self.name.as_slice() == *name)
+    }
+
+    // The shadow root a template element declares, if it is a declarative shadow root.
+    pub fn declarative_shadow_root(&self) -> Option<ShadowRootInit> {
+        if self.ns != HtmlNs || self.name.as_slice() != "template" {
+            return None
+        }
+        let mode = match self.get_attribute("shadowrootmode") {
+            Some(mode) if mode.eq_ignore_ascii_case("open") => OpenShadowRoot,
+            Some(mode) if mode.eq_ignore_ascii_case("closed") => ClosedShadowRoot,
+            _ => return None
+        };
+        Some(ShadowRootInit {
+            mode: mode,
+            delegates_focus: self.has_attribute("shadowrootdelegatesfocus"),
+            clonable: self.has_attribute("shadowrootclonable"),
+        })
+    }
+}
+
 // FIXME: This is terribly type-unsafe. But we don't have working generic extern functions yet...
 pub type NodeDataPtr = uint;
 
@@ -62,7 +136,7 @@ pub struct TreeHandler<'a> {
     pub ref_node: |node: NodeDataPtr|: 'a,
     pub unref_node: |node: NodeDataPtr|: 'a,
     pub append_child: |parent: NodeDataPtr, child: NodeDataPtr|: 'a -> NodeDataPtr,
-    pub insert_before: |parent: NodeDataPtr, child: NodeDataPtr|: 'a -> NodeDataPtr,
+    pub insert_before: |parent: NodeDataPtr, child: NodeDataPtr, reference: NodeDataPtr|: 'a -> NodeDataPtr,
     pub remove_child: |parent: NodeDataPtr, child: NodeDataPtr|: 'a -> NodeDataPtr,
     pub clone_node: |node: NodeDataPtr, deep: bool|: 'a -> NodeDataPtr,
     pub reparent_children: |node: NodeDataPtr, new_parent: NodeDataPtr|: 'a -> NodeDataPtr,
@@ -74,16 +148,511 @@ pub struct TreeHandler<'a> {
     pub encoding_change: |encname: String|: 'a,
     pub complete_script: |script: NodeDataPtr|: 'a,
     pub complete_style: |style: NodeDataPtr|: 'a,
+    // Returns the document fragment holding the contents of a template element, which is where
+    // the children the parser inserts into the template go.
+    pub get_template_contents: |template: NodeDataPtr|: 'a -> NodeDataPtr,
+    // Attaches a shadow root to `host` for a declarative shadow root template. Returns the shadow
+    // root, or null if the host can't have one, in which case the template is inserted as usual.
+    pub attach_shadow_root: |host: NodeDataPtr, init: ShadowRootInit|: 'a -> NodeDataPtr,
+    // Called when an element is popped off the stack of open elements, after which the parser
+    // adds nothing more to it in well-formed markup. Hubbub doesn't report this, so it is
+    // inferred from where nodes are inserted, which can be later than the element is popped
+    // but never earlier; elements still open at the end are finished when the parser completes.
+    pub element_finished: |element: NodeDataPtr|: 'a,
+}
+
+// Watches the tree a tree handler builds, through `observe_tree`. The methods are called after
+// the tree handler has handled the corresponding callback, with the node it returned.
+pub trait TreeObserver {
+    // Called before an element is created, since its tag moves into the tree handler.
+    fn creating_element(&mut self, _tag: &Tag) {}
+    fn element_created(&mut self, _element: NodeDataPtr) {}
+    fn child_appended(&mut self, _parent: NodeDataPtr, _node: NodeDataPtr) {}
+    fn child_inserted(&mut self, _parent: NodeDataPtr, _node: NodeDataPtr,
+                      _reference: NodeDataPtr) {}
+    fn child_removed(&mut self, _parent: NodeDataPtr, _child: NodeDataPtr) {}
+    fn children_reparented(&mut self, _node: NodeDataPtr, _new_parent: NodeDataPtr) {}
+    fn template_contents_used(&mut self, _template: NodeDataPtr, _contents: NodeDataPtr) {}
+    fn element_finished(&mut self, _element: NodeDataPtr) {}
+    // Called for each reference the parser takes to a node, including the one that comes with
+    // each node a callback returns, and for each reference it drops with `unref_node`.
+    fn node_referenced(&mut self, _node: NodeDataPtr) {}
+    fn node_released(&mut self, _node: NodeDataPtr) {}
+}
+
+// Wraps `tree_handler` in one that also reports to `observer`, and hands it to `f`. The observer
+// may be borrowed in `f` as long as the parser isn't running.
+pub fn observe_tree<O: TreeObserver, R>(tree_handler: &mut TreeHandler, observer: &RefCell<O>,
+                                        f: |&mut TreeHandler| -> R) -> R {
+    let inner = RefCell::new(tree_handler);
+    let mut handler = TreeHandler {
+        create_comment: |data| {
+            let node = (inner.borrow_mut().create_comment)(data);
+            observer.borrow_mut().node_referenced(node);
+            node
+        },
+        create_doctype: |doctype| {
+            let node = (inner.borrow_mut().create_doctype)(doctype);
+            observer.borrow_mut().node_referenced(node);
+            node
+        },
+        create_element: |tag| {
+            observer.borrow_mut().creating_element(&*tag);
+            let node = (inner.borrow_mut().create_element)(tag);
+            observer.borrow_mut().node_referenced(node);
+            observer.borrow_mut().element_created(node);
+            node
+        },
+        create_text: |data| {
+            let node = (inner.borrow_mut().create_text)(data);
+            observer.borrow_mut().node_referenced(node);
+            node
+        },
+        ref_node: |node| {
+            (inner.borrow_mut().ref_node)(node);
+            observer.borrow_mut().node_referenced(node);
+        },
+        unref_node: |node| {
+            (inner.borrow_mut().unref_node)(node);
+            observer.borrow_mut().node_released(node);
+        },
+        append_child: |parent, child| {
+            let node = (inner.borrow_mut().append_child)(parent, child);
+            observer.borrow_mut().node_referenced(node);
+            observer.borrow_mut().child_appended(parent, node);
+            node
+        },
+        insert_before: |parent, child, reference| {
+            let node = (inner.borrow_mut().insert_before)(parent, child, reference);
+            observer.borrow_mut().node_referenced(node);
+            observer.borrow_mut().child_inserted(parent, node, reference);
+            node
+        },
+        remove_child: |parent, child| {
+            let node = (inner.borrow_mut().remove_child)(parent, child);
+            observer.borrow_mut().node_referenced(node);
+            observer.borrow_mut().child_removed(parent, child);
+            node
+        },
+        clone_node: |node, deep| {
+            let clone = (inner.borrow_mut().clone_node)(node, deep);
+            observer.borrow_mut().node_referenced(clone);
+            clone
+        },
+        reparent_children: |node, new_parent| {
+            let result = (inner.borrow_mut().reparent_children)(node, new_parent);
+            observer.borrow_mut().children_reparented(node, new_parent);
+            result
+        },
+        get_parent: |node, element_only| {
+            let parent = (inner.borrow_mut().get_parent)(node, element_only);
+            if parent != 0 {
+                observer.borrow_mut().node_referenced(parent);
+            }
+            parent
+        },
+        has_children: |node| (inner.borrow_mut().has_children)(node),
+        form_associate: |form, node| (inner.borrow_mut().form_associate)(form, node),
+        add_attributes: |node, attributes| (inner.borrow_mut().add_attributes)(node, attributes),
+        set_quirks_mode: |mode| (inner.borrow_mut().set_quirks_mode)(mode),
+        encoding_change: |encname| (inner.borrow_mut().encoding_change)(encname),
+        complete_script: |script| (inner.borrow_mut().complete_script)(script),
+        complete_style: |style| (inner.borrow_mut().complete_style)(style),
+        get_template_contents: |template| {
+            let contents = (inner.borrow_mut().get_template_contents)(template);
+            observer.borrow_mut().template_contents_used(template, contents);
+            contents
+        },
+        attach_shadow_root: |host, init| (inner.borrow_mut().attach_shadow_root)(host, init),
+        element_finished: |element| {
+            (inner.borrow_mut().element_finished)(element);
+            observer.borrow_mut().element_finished(element);
+        },
+    };
+    f(&mut handler)
+}
+
+pub enum Token {
+    DoctypeToken(Box<Doctype>),
+    StartTagToken(Box<Tag>),
+    EndTagToken(Box<Tag>),
+    CommentToken(String),
+    CharacterToken(String),
+    EofToken,
+}
+
+// Setting a token handler replaces the tree builder: the parser then only tokenizes its input.
+pub struct TokenHandler<'a> {
+    pub process_token: |token: Token|: 'a,
+}
+
+// What the parser should do after a script element has been run.
+pub enum ScriptAction {
+    ContinueParsing,
+    // Stop tokenizing, for instance until an external script has loaded. `Parser::resume`
+    // carries on from where the parser stopped.
+    PauseParsing,
+    // Insert markup at the current insertion point, as `document.write` does.
+    WriteMarkup(String),
+}
+
+// Runs the script elements the parser finishes, with the attributes of the element and the
+// concatenated text of its children as source.
+pub trait ScriptRunner {
+    fn run_script(&mut self, script: NodeDataPtr, attributes: &[Attribute], source: &str)
+                  -> ScriptAction;
+}
+
+pub enum StylesheetSource {
+    // The text of a `<style>` element.
+    InlineStylesheet(String),
+    // The unresolved `href` of a `<link rel=stylesheet>` element.
+    ExternalStylesheet(String),
+}
+
+pub struct Stylesheet {
+    pub node: NodeDataPtr,
+    pub source: StylesheetSource,
+    pub media: Option<String>,
+    pub title: Option<String>,
+    pub alternate: bool,
+    // The position of the stylesheet among all the stylesheets of the document.
+    pub order: uint,
+}
+
+// Receives the stylesheets of a document in document order, as soon as each is complete.
+pub trait StyleObserver {
+    fn stylesheet_found(&mut self, stylesheet: Stylesheet);
+}
+
+// An element whose text content is collected as it is parsed.
+pub struct TextElement {
+    pub attributes: Vec<Attribute>,
+    pub text: String,
 }
 
 pub struct TreeHandlerPair<'a> {
+    pub hubbub_parser: *mut ll::Parser,
     pub tree_handler: &'a mut TreeHandler<'a>,
-    pub ll_tree_handler: ll::TreeHandler
+    pub ll_tree_handler: ll::TreeHandler,
+    // Hubbub doesn't know about template elements, so they are tracked here as they are created.
+    // Its tree builder otherwise handles them as ordinary elements: a template before `<body>`
+    // starts the body rather than going in the head.
+    pub template_nodes: HashSet<NodeDataPtr>,
+    // Declarative shadow root templates that haven't been inserted yet, and the shadow roots
+    // attached for the ones that have.
+    pub pending_shadow_roots: HashMap<NodeDataPtr, ShadowRootInit>,
+    pub shadow_roots: HashMap<NodeDataPtr, NodeDataPtr>,
+    pub script_runner: Option<&'a mut ScriptRunner+'a>,
+    pub style_observer: Option<&'a mut StyleObserver+'a>,
+    pub stylesheet_count: uint,
+    // Elements whose text is being collected, and the last text node created, which is about to
+    // be inserted somewhere.
+    pub text_elements: HashMap<NodeDataPtr, TextElement>,
+    pub last_text: Option<(NodeDataPtr, String)>,
+    // Elements and text nodes created but not inserted yet, clones of elements not yet counted
+    // as open, and the elements that are open as far as can be told from where nodes are
+    // inserted, starting with the document node.
+    pub new_elements: HashSet<NodeDataPtr>,
+    pub new_text_nodes: HashSet<NodeDataPtr>,
+    pub new_clones: HashSet<NodeDataPtr>,
+    pub open_elements: Vec<NodeDataPtr>,
+}
+
+impl<'a> TreeHandlerPair<'a> {
+    // The node that children inserted into `node` actually go into.
+    pub fn children_parent(&mut self, node: NodeDataPtr) -> NodeDataPtr {
+        match self.shadow_roots.find(&node) {
+            Some(&shadow_root) => return shadow_root,
+            None => {}
+        }
+        if self.template_nodes.contains(&node) {
+            (self.tree_handler.get_template_contents)(node)
+        } else {
+            node
+        }
+    }
+
+    // Called when `child` is about to be inserted into `parent`, as the parser sees it. A
+    // declarative shadow root template attaches a shadow root to its parent instead of being
+    // inserted, unless the parent is a template itself or already a declarative shadow root's;
+    // returns true if that happened. The template still counts as open.
+    pub fn attach_declarative_shadow_root(&mut self, parent: NodeDataPtr, child: NodeDataPtr)
+                                          -> bool {
+        let init = match self.pending_shadow_roots.pop(&child) {
+            Some(init) => init,
+            None => return false
+        };
+        if self.template_nodes.contains(&parent) || self.shadow_roots.contains_key(&parent) {
+            return false
+        }
+        let shadow_root = (self.tree_handler.attach_shadow_root)(parent, init);
+        if shadow_root == 0 {
+            return false
+        }
+        self.shadow_roots.insert(child, shadow_root);
+        true
+    }
+
+    // Starts collecting the text of an element being created, if it is needed later.
+    pub fn text_element_for(&self, tag: &Tag) -> Option<TextElement> {
+        let collect_text = tag.ns == HtmlNs && match tag.name.as_slice() {
+            "script" => self.script_runner.is_some(),
+            "style" => self.style_observer.is_some(),
+            _ => false
+        };
+        if collect_text {
+            Some(TextElement {
+                attributes: tag.attributes.clone(),
+                text: String::new(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn node_inserted(&mut self, parent: NodeDataPtr, child: NodeDataPtr) {
+        let text = match self.last_text.take() {
+            Some((node, text)) if node == child => text,
+            _ => return
+        };
+        match self.text_elements.find_mut(&parent) {
+            Some(element) => element.text.push_str(text.as_slice()),
+            None => {}
+        }
+    }
+
+    pub fn run_script(&mut self, script: NodeDataPtr) {
+        let element = match self.text_elements.pop(&script) {
+            Some(element) => element,
+            None => return
+        };
+        (self.tree_handler.unref_node)(script);
+        let action = match self.script_runner {
+            Some(ref mut script_runner) => {
+                script_runner.run_script(script, element.attributes.as_slice(),
+                                         element.text.as_slice())
+            }
+            None => return
+        };
+        match action {
+            ContinueParsing => {}
+            PauseParsing => {
+                debug!("pausing for script");
+                set_paused(self.hubbub_parser, true);
+            }
+            WriteMarkup(markup) => unsafe {
+                debug!("writing markup from script");
+                let hubbub_error = ll::parser::hubbub_parser_insert_chunk(self.hubbub_parser,
+                                                                          markup.as_ptr(),
+                                                                          markup.len() as size_t);
+                assert!(hubbub_error == ll::OK || hubbub_error == ll::PAUSED);
+            }
+        }
+    }
+
+    // Reports `<link rel=stylesheet>` elements, which are complete as soon as they are created.
+    pub fn link_created(&mut self, node: NodeDataPtr, tag: &Tag) {
+        if self.style_observer.is_none() || tag.ns != HtmlNs || tag.name.as_slice() != "link" {
+            return
+        }
+        let rel = tag.get_attribute("rel").unwrap_or("");
+        let has_rel = |keyword: &str| rel.split(is_html_whitespace).any(|token| {
+            token.eq_ignore_ascii_case(keyword)
+        });
+        if !has_rel("stylesheet") {
+            return
+        }
+        let href = match tag.get_attribute("href") {
+            Some(href) => href.to_string(),
+            None => return
+        };
+        let alternate = has_rel("alternate");
+        self.report_stylesheet(node, ExternalStylesheet(href), tag.attributes.as_slice(),
+                               alternate);
+    }
+
+    // Called when `child` has been inserted into `parent`, before `reference` unless it is null,
+    // with `parent` as the parser sees it. Hubbub doesn't say when it pops elements off its stack
+    // of open elements, so this keeps a copy of the stack up to date from where nodes go:
+    //
+    // - A node the parser has just created goes into the current node, so appending it to an
+    //   element means the elements opened after that one have been closed. Inserting it before
+    //   another node, as when foster parenting, closes nothing.
+    // - When the adoption agency algorithm moves open elements, or clones containing them, the
+    //   elements that were between their new parent and them are closed, and the clones take
+    //   their place. The open elements after them stay open.
+    //
+    // Elements can be finished late, but never while the parser can still add to them.
+    pub fn update_open_elements(&mut self, parent: NodeDataPtr, child: NodeDataPtr,
+                                reference: NodeDataPtr) {
+        if self.new_elements.contains(&child) || self.new_text_nodes.remove(&child) {
+            if reference == 0 {
+                self.finish_open_elements_after(parent);
+            }
+            if self.new_elements.remove(&child) {
+                self.open_elements.push(child);
+            }
+            return
+        }
+
+        let clone = self.new_clones.remove(&child);
+        // Comments, and nodes the parser is done with, contain no open elements.
+        if !clone && !self.open_elements.contains(&child) &&
+                !(self.tree_handler.has_children)(child) {
+            return
+        }
+        let mut first_moved = None;
+        for index in range(1, self.open_elements.len()) {
+            let open = *self.open_elements.get(index);
+            if self.is_inclusive_ancestor(child, open) {
+                first_moved = Some(index);
+                break
+            }
+        }
+        let mut index = match first_moved {
+            Some(index) => index,
+            None => {
+                // A clone with no open elements in it, such as a formatting element being
+                // reconstructed, is the new current node.
+                if clone {
+                    self.open_elements.push(child);
+                }
+                return
+            }
+        };
+
+        // When foster parenting, the moved elements go before the table, which stays open.
+        let anchor = if reference != 0 && self.open_elements.contains(&reference) {
+            reference
+        } else {
+            parent
+        };
+        match self.open_elements.slice_to(index).iter().rposition(|&open| open == anchor) {
+            Some(anchor_index) => {
+                for closed in range(anchor_index + 1, index).rev() {
+                    let element = self.open_elements.remove(closed).unwrap();
+                    (self.tree_handler.element_finished)(element);
+                }
+                index = anchor_index + 1;
+            }
+            None => {}
+        }
+
+        let mut clones = Vec::new();
+        let mut node = *self.open_elements.get(index);
+        while node != child && node != 0 {
+            node = self.parent(node);
+            if (node == child && clone) || self.new_clones.remove(&node) {
+                clones.push(node);
+            }
+        }
+        for &element in clones.iter() {
+            self.open_elements.insert(index, element);
+        }
+    }
+
+    // Whether `node` is `descendant` or one of its ancestors.
+    fn is_inclusive_ancestor(&mut self, node: NodeDataPtr, descendant: NodeDataPtr) -> bool {
+        let mut current = descendant;
+        while current != 0 {
+            if current == node {
+                return true
+            }
+            current = self.parent(current);
+        }
+        false
+    }
+
+    // The parent of `node`, without keeping the reference that comes with it.
+    fn parent(&mut self, node: NodeDataPtr) -> NodeDataPtr {
+        let parent = (self.tree_handler.get_parent)(node, false);
+        if parent != 0 {
+            (self.tree_handler.unref_node)(parent);
+        }
+        parent
+    }
+
+    // Forgets what was known about a node the tree handler has just created, whose slot may
+    // have held a node that was freed before being inserted.
+    fn node_created(&mut self, node: NodeDataPtr) {
+        self.new_elements.remove(&node);
+        self.new_text_nodes.remove(&node);
+        self.new_clones.remove(&node);
+    }
+
+    fn finish_open_elements_after(&mut self, parent: NodeDataPtr) {
+        match self.open_elements.iter().rposition(|&open| open == parent) {
+            Some(index) => {
+                while self.open_elements.len() > index + 1 {
+                    let element = self.open_elements.pop().unwrap();
+                    (self.tree_handler.element_finished)(element);
+                }
+            }
+            None => {}
+        }
+    }
+
+    // Finishes the elements still open at the end of the input.
+    pub fn finish_open_elements(&mut self) {
+        while self.open_elements.len() > 1 {
+            let element = self.open_elements.pop().unwrap();
+            (self.tree_handler.element_finished)(element);
+        }
+    }
+
+    pub fn style_completed(&mut self, style: NodeDataPtr) {
+        if self.style_observer.is_none() {
+            return
+        }
+        match self.text_elements.pop(&style) {
+            Some(element) => {
+                (self.tree_handler.unref_node)(style);
+                self.report_stylesheet(style, InlineStylesheet(element.text),
+                                       element.attributes.as_slice(), false)
+            }
+            None => {}
+        }
+    }
+
+    fn report_stylesheet(&mut self, node: NodeDataPtr, source: StylesheetSource,
+                         attributes: &[Attribute], alternate: bool) {
+        let attribute = |name: &str| attributes.iter().find(|attribute| {
+            attribute.ns == NullNs && attribute.name.as_slice() == name
+        }).map(|attribute| attribute.value.clone());
+        let stylesheet = Stylesheet {
+            node: node,
+            source: source,
+            media: attribute("media"),
+            title: attribute("title"),
+            alternate: alternate,
+            order: self.stylesheet_count,
+        };
+        self.stylesheet_count += 1;
+        match self.style_observer {
+            Some(ref mut style_observer) => style_observer.stylesheet_found(stylesheet),
+            None => {}
+        }
+    }
+}
+
+pub fn is_html_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n' || c == '\x0c' || c == '\r'
+}
+
+fn set_paused(hubbub_parser: *mut ll::Parser, paused: bool) {
+    unsafe {
+        let hubbub_error = ll::parser::hubbub_parser_setopt(hubbub_parser,
+                                                            ll::PARSER_PAUSE,
+                                                            mem::transmute(&paused));
+        assert!(hubbub_error == ll::OK || hubbub_error == ll::PAUSED);
+    }
 }
 
 pub struct Parser<'a> {
     pub hubbub_parser: *mut ll::Parser,
     pub tree_handler: Option<TreeHandlerPair<'a>>,
+    pub token_handler: Option<&'a mut TokenHandler<'a>>,
 }
 
 #[unsafe_destructor]
@@ -105,12 +674,14 @@ impl<'a> Parser<'a> {
 
         Parser {
             hubbub_parser: hubbub_parser,
-            tree_handler: None
+            tree_handler: None,
+            token_handler: None,
         }
     }
 
     pub fn set_tree_handler(&mut self, tree_handler: &'a mut TreeHandler<'a>) {
         self.tree_handler = Some(TreeHandlerPair {
+            hubbub_parser: self.hubbub_parser,
             tree_handler: tree_handler,
             ll_tree_handler: ll::TreeHandler {
                 create_comment: tree_callbacks::create_comment,
@@ -133,7 +704,19 @@ impl<'a> Parser<'a> {
                 complete_script: tree_callbacks::complete_script,
                 complete_style: tree_callbacks::complete_style,
                 ctx: unsafe { mem::transmute(&self.tree_handler) },
-            }
+            },
+            template_nodes: HashSet::new(),
+            pending_shadow_roots: HashMap::new(),
+            shadow_roots: HashMap::new(),
+            script_runner: None,
+            style_observer: None,
+            stylesheet_count: 0,
+            text_elements: HashMap::new(),
+            last_text: None,
+            new_elements: HashSet::new(),
+            new_text_nodes: HashSet::new(),
+            new_clones: HashSet::new(),
+            open_elements: Vec::new(),
         });
 
         let ptr: *mut ll::TreeHandler =
@@ -147,7 +730,45 @@ impl<'a> Parser<'a> {
         }
     }
 
+    pub fn set_token_handler(&mut self, token_handler: &'a mut TokenHandler<'a>) {
+        self.token_handler = Some(token_handler);
+
+        let mut params = ll::ParserOptParamsTokenHandler {
+            handler: unsafe { mem::transmute(token_callbacks::process_token) },
+            pw: unsafe { mem::transmute(&self.token_handler) },
+        };
+
+        unsafe {
+            let hubbub_error = ll::parser::hubbub_parser_setopt(self.hubbub_parser,
+                                                                ll::PARSER_TOKEN_HANDLER,
+                                                                mem::transmute(&mut params));
+            assert!(hubbub_error == ll::OK);
+        }
+    }
+
+    // Must be called after `set_tree_handler`.
+    pub fn set_script_runner(&mut self, script_runner: &'a mut ScriptRunner+'a) {
+        match self.tree_handler {
+            Some(ref mut pair) => pair.script_runner = Some(script_runner),
+            None => fail!("set_script_runner called before set_tree_handler")
+        }
+    }
+
+    // Must be called after `set_tree_handler`. Enables styling, so that the parser reports
+    // completed `<style>` elements.
+    pub fn set_style_observer(&mut self, style_observer: &'a mut StyleObserver+'a) {
+        match self.tree_handler {
+            Some(ref mut pair) => pair.style_observer = Some(style_observer),
+            None => fail!("set_style_observer called before set_tree_handler")
+        }
+        self.enable_styling(true);
+    }
+
     pub fn set_document_node(&mut self, node: NodeDataPtr) {
+        match self.tree_handler {
+            Some(ref mut pair) => pair.open_elements = vec!(node),
+            None => {}
+        }
         unsafe {
             debug!("setting document node");
             let hubbub_error = ll::parser::hubbub_parser_setopt(self.hubbub_parser,
@@ -157,6 +778,21 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Switches the tokenizer to the given content model, as the tree builder does after the
+    // start tag of a `<textarea>`, `<style>` or `<plaintext>` element.
+    pub fn set_content_model(&mut self, content_model: ContentModel) {
+        unsafe {
+            debug!("setting content model");
+            let mut params = ll::ParserOptParamsContentModel {
+                content_model: tree_callbacks::to_hubbub_content_model(content_model)
+            };
+            let hubbub_error = ll::parser::hubbub_parser_setopt(self.hubbub_parser,
+                                                                ll::PARSER_CONTENT_MODEL,
+                                                                mem::transmute(&mut params));
+            assert!(hubbub_error == ll::OK);
+        }
+    }
+
     pub fn enable_scripting(&mut self, enable: bool) {
         unsafe {
             debug!("enabling scripting");
@@ -183,7 +819,7 @@ impl<'a> Parser<'a> {
             let ptr = data.as_ptr();
             let hubbub_error = ll::parser::hubbub_parser_parse_chunk(self.hubbub_parser, ptr,
                                                                      data.len() as size_t);
-            assert!(hubbub_error == ll::OK);
+            assert!(hubbub_error == ll::OK || hubbub_error == ll::PAUSED);
         }
     }
 
@@ -193,16 +829,259 @@ impl<'a> Parser<'a> {
             let ptr = data.as_ptr();
             let hubbub_error = ll::parser::hubbub_parser_insert_chunk(self.hubbub_parser, ptr,
                                                                       data.len() as size_t);
-            assert!(hubbub_error == ll::OK);
+            assert!(hubbub_error == ll::OK || hubbub_error == ll::PAUSED);
         }
     }
 
-    pub fn completed(&self) {
+    pub fn pause(&mut self) {
+        debug!("pausing");
+        set_paused(self.hubbub_parser, true);
+    }
+
+    // Resumes a paused parse, tokenizing the input buffered so far.
+    pub fn resume(&mut self) {
+        debug!("resuming");
+        set_paused(self.hubbub_parser, false);
+    }
+
+    // The charset the input is decoded as, if one has been chosen, and how it was determined.
+    pub fn read_charset(&mut self) -> (Option<String>, CharsetSource) {
+        let mut source = ll::CHARSET_UNKNOWN;
+        let charset = unsafe {
+            ll::parser::hubbub_parser_read_charset(self.hubbub_parser, &mut source)
+        };
+        let charset = if charset.is_null() {
+            None
+        } else {
+            Some(unsafe { string::raw::from_buf(charset as *const u8) })
+        };
+        (charset, tree_callbacks::from_hubbub_charset_source(source))
+    }
+
+    pub fn completed(&mut self) {
         unsafe {
             debug!("completing");
             let hubbub_error = ll::parser::hubbub_parser_completed(self.hubbub_parser);
             assert!(hubbub_error == ll::OK);
         }
+        match self.tree_handler {
+            Some(ref mut pair) => pair.finish_open_elements(),
+            None => {}
+        }
+    }
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum ResourceType {
+    ScriptResource,
+    StylesheetResource,
+    ImageResource,
+    // A `<link rel=preload>`, with the value of its `as` attribute.
+    PreloadResource(String),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct PreloadRequest {
+    pub url: String,
+    pub resource_type: ResourceType,
+}
+
+// Finds the resources referenced by markup the parser hasn't got to yet, for instance while it
+// is paused on a blocking script, so that they can be fetched early. The scanner has its own
+// tokenizer and builds no tree. Successive calls to `scan` carry on with the next part of the
+// input, which can be split anywhere: whatever the tokenizer hadn't finished with at the end of
+// one part, such as a tag, a comment or a script, is scanned again along with the next.
+pub struct PreloadScanner {
+    base_url: String,
+    base_seen: bool,
+    template_depth: uint,
+    // The input since the tokenizer was last known to be between tokens, and the raw text
+    // element it is in, if any.
+    pending: Vec<u8>,
+    raw_text: Option<String>,
+}
+
+impl PreloadScanner {
+    pub fn new(document_url: &str) -> PreloadScanner {
+        PreloadScanner {
+            base_url: document_url.to_string(),
+            base_seen: false,
+            template_depth: 0,
+            pending: Vec::new(),
+            raw_text: None,
+        }
+    }
+
+    // The URL that relative URLs are resolved against: the first `<base href>` seen, or the
+    // document's URL.
+    pub fn base_url<'a>(&'a self) -> &'a str {
+        self.base_url.as_slice()
+    }
+
+    pub fn scan(&mut self, data: &[u8]) -> Vec<PreloadRequest> {
+        let mut input = mem::replace(&mut self.pending, Vec::new());
+        input.push_all(data);
+        let resumed_raw_text = self.raw_text.take();
+        let mut raw_text = resumed_raw_text.clone();
+        let mut requests = Vec::new();
+        let content_model = Cell::new(None);
+        let token_ended = Cell::new(false);
+        let replaying = Cell::new(false);
+        let mut between_tokens = 0;
+        {
+            let mut token_handler = TokenHandler {
+                process_token: |token| {
+                    match token {
+                        _ if replaying.get() => {}
+                        StartTagToken(tag) => {
+                            token_ended.set(true);
+                            match self.start_tag(&*tag) {
+                                Some(request) => requests.push(request),
+                                None => {}
+                            }
+                            let model = raw_text_content_model(tag.name.as_slice());
+                            if model.is_some() {
+                                raw_text = Some(tag.name.clone());
+                            }
+                            content_model.set(model);
+                        }
+                        EndTagToken(tag) => {
+                            token_ended.set(true);
+                            if tag.name.as_slice() == "template" && self.template_depth > 0 {
+                                self.template_depth -= 1;
+                            } else if raw_text_content_model(tag.name.as_slice()).is_some() {
+                                raw_text = None;
+                                content_model.set(Some(PCData));
+                            }
+                        }
+                        DoctypeToken(_) | CommentToken(_) => token_ended.set(true),
+                        _ => {}
+                    }
+                }
+            };
+            let mut parser = Parser::new("UTF-8", true);
+            parser.set_token_handler(&mut token_handler);
+
+            // The tokenizer only ends raw text at an end tag matching the last start tag it saw,
+            // so the element the previous part ended in is opened again first.
+            match resumed_raw_text {
+                Some(ref name) => {
+                    replaying.set(true);
+                    parser.parse_chunk(format!("<{}>", name).as_bytes());
+                    replaying.set(false);
+                    parser.set_content_model(raw_text_content_model(name.as_slice()).unwrap());
+                }
+                None => {}
+            }
+
+            // Without a tree builder nothing switches the tokenizer into raw text after a
+            // `<script>` or `<style>` start tag, so the input is fed up to the end of each tag
+            // in turn and the content model switched in between. The input after the last `>`
+            // can't finish a token, so it is left for the next part.
+            let mut start = 0;
+            for (index, &byte) in input.iter().enumerate() {
+                if byte != '>' as u8 {
+                    continue
+                }
+                parser.parse_chunk(input.slice(start, index + 1));
+                start = index + 1;
+                if token_ended.get() {
+                    between_tokens = start;
+                    token_ended.set(false);
+                }
+                match content_model.get() {
+                    Some(model) => {
+                        parser.set_content_model(model);
+                        content_model.set(None);
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        // Raw text only ends at an end tag, so none of it before the last `<` is needed again.
+        if raw_text.is_some() {
+            between_tokens = match input.slice_from(between_tokens).iter().rposition(|&byte| {
+                byte == '<' as u8
+            }) {
+                Some(index) => between_tokens + index,
+                None => input.len()
+            };
+        }
+        self.pending = input.slice_from(between_tokens).to_vec();
+        self.raw_text = raw_text;
+        requests
+    }
+
+    fn start_tag(&mut self, tag: &Tag) -> Option<PreloadRequest> {
+        let name = tag.name.as_slice();
+        if name == "template" {
+            self.template_depth += 1;
+        }
+        // Template contents are inert, so nothing in them gets fetched.
+        if self.template_depth > 0 {
+            return None
+        }
+        let (href, resource_type) = match name {
+            "base" => {
+                match tag.get_attribute("href") {
+                    Some(href) if !self.base_seen => {
+                        self.base_seen = true;
+                        match url::resolve(self.base_url.as_slice(), href) {
+                            Some(base_url) => self.base_url = base_url,
+                            None => {}
+                        }
+                    }
+                    _ => {}
+                }
+                return None
+            }
+            "script" => (tag.get_attribute("src"), ScriptResource),
+            "img" => (tag.get_attribute("src"), ImageResource),
+            "link" => {
+                let rel = tag.get_attribute("rel").unwrap_or("");
+                let has_rel = |keyword: &str| rel.split(is_html_whitespace).any(|token| {
+                    token.eq_ignore_ascii_case(keyword)
+                });
+                if has_rel("stylesheet") && !has_rel("alternate") {
+                    (tag.get_attribute("href"), StylesheetResource)
+                } else if has_rel("preload") {
+                    match tag.get_attribute("as") {
+                        Some(destination) => {
+                            (tag.get_attribute("href"),
+                             PreloadResource(destination.to_ascii_lower()))
+                        }
+                        None => return None
+                    }
+                } else {
+                    return None
+                }
+            }
+            _ => return None
+        };
+        let href = match href {
+            Some(href) if !url::clean_url(href).is_empty() => href,
+            _ => return None
+        };
+        url::resolve(self.base_url.as_slice(), href).map(|url| {
+            PreloadRequest {
+                url: url,
+                resource_type: resource_type,
+            }
+        })
+    }
+}
+
+// The content model the tree builder would switch the tokenizer to after a start tag, with
+// scripting enabled.
+pub fn raw_text_content_model(name: &str) -> Option<ContentModel> {
+    match name {
+        "title" | "textarea" => Some(RCData),
+        "style" | "script" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => {
+            Some(CData)
+        }
+        "plaintext" => Some(PlainText),
+        _ => None
     }
 }
 
@@ -214,6 +1093,8 @@ pub mod tree_callbacks {
     use std::string;
     use super::{NodeDataPtr, Ns, NullNs, HtmlNs, MathMlNs, SvgNs, XLinkNs, XmlNs, XmlNsNs};
     use super::{QuirksMode, NoQuirks, LimitedQuirks, FullQuirks};
+    use super::{ContentModel, PCData, RCData, CData, PlainText};
+    use super::{CharsetSource, CharsetUnknown, CharsetTentative, CharsetConfident};
     use super::{Attribute, Tag, Doctype, TreeHandlerPair};
     use ll;
 
@@ -254,6 +1135,15 @@ pub mod tree_callbacks {
         }
     }
 
+    pub fn from_hubbub_charset_source(source: ll::CharsetSource) -> CharsetSource {
+        match source {
+            0 => CharsetUnknown,
+            1 => CharsetTentative,
+            2 => CharsetConfident,
+            _ => fail!("unknown charset source")
+        }
+    }
+
     pub fn from_hubbub_attributes(attributes: *mut ll::Attribute, n_attributes: u32) -> Vec<Attribute> {
         debug!("from_hubbub_attributes n={:u}", n_attributes as uint);
         unsafe {
@@ -300,6 +1190,15 @@ pub mod tree_callbacks {
         unsafe { mem::transmute(node) }
     }
 
+    pub fn to_hubbub_content_model(content_model: ContentModel) -> ll::ContentModel {
+        match content_model {
+            PCData => ll::CONTENT_MODEL_PCDATA,
+            RCData => ll::CONTENT_MODEL_RCDATA,
+            CData => ll::CONTENT_MODEL_CDATA,
+            PlainText => ll::CONTENT_MODEL_PLAINTEXT,
+        }
+    }
+
     // Callbacks
 
     pub extern fn create_comment(ctx: *mut c_void, data: *mut ll::String, result: *mut *mut c_void)
@@ -332,8 +1231,40 @@ pub mod tree_callbacks {
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
         unsafe {
-            let tag: &ll::Tag = mem::transmute(tag);
-            *result = to_hubbub_node((this.tree_handler.create_element)(from_hubbub_tag(tag)));
+            let tag = from_hubbub_tag(mem::transmute(tag));
+            let is_template = tag.ns == HtmlNs && tag.name.as_slice() == "template";
+            let shadow_root = tag.declarative_shadow_root();
+            let text_element = this.text_element_for(&*tag);
+            let link = if this.style_observer.is_some() && tag.name.as_slice() == "link" {
+                Some(tag.clone())
+            } else {
+                None
+            };
+            let node = (this.tree_handler.create_element)(tag);
+            match link {
+                Some(link) => this.link_created(node, &*link),
+                None => {}
+            }
+            // Nodes remembered here are referenced like the parser's own, so that the tree
+            // handler doesn't reuse them for other nodes.
+            match text_element {
+                Some(text_element) => {
+                    this.text_elements.insert(node, text_element);
+                    (this.tree_handler.ref_node)(node);
+                }
+                None => {}
+            }
+            this.node_created(node);
+            this.new_elements.insert(node);
+            if is_template {
+                this.template_nodes.insert(node);
+                (this.tree_handler.ref_node)(node);
+            }
+            match shadow_root {
+                Some(init) => { this.pending_shadow_roots.insert(node, init); }
+                None => {}
+            }
+            *result = to_hubbub_node(node);
         }
         return ll::OK;
     }
@@ -344,8 +1275,18 @@ pub mod tree_callbacks {
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
         unsafe {
-            let data: &ll::String = mem::transmute(data);
-            *result = to_hubbub_node((this.tree_handler.create_text)(from_hubbub_string(data)));
+            let data = from_hubbub_string(mem::transmute(data));
+            // Only keep a copy of the text if some element is collecting its text.
+            let node = if !this.text_elements.is_empty() {
+                let node = (this.tree_handler.create_text)(data.clone());
+                this.last_text = Some((node, data));
+                node
+            } else {
+                (this.tree_handler.create_text)(data)
+            };
+            this.node_created(node);
+            this.new_text_nodes.insert(node);
+            *result = to_hubbub_node(node);
         }
         return ll::OK;
     }
@@ -371,21 +1312,45 @@ pub mod tree_callbacks {
         debug!("ll append child");
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
+        let hubbub_parent = from_hubbub_node(parent);
+        let parent = this.children_parent(hubbub_parent);
+        let child = from_hubbub_node(child);
+        if this.attach_declarative_shadow_root(hubbub_parent, child) {
+            // The parser takes a reference with the result, as when the child is inserted.
+            (this.tree_handler.ref_node)(child);
+            this.update_open_elements(hubbub_parent, child, 0);
+            unsafe { *result = to_hubbub_node(child); }
+            return ll::OK;
+        }
+        this.node_inserted(parent, child);
+        let node = (this.tree_handler.append_child)(parent, child);
+        this.update_open_elements(hubbub_parent, child, 0);
         unsafe {
-            *result = to_hubbub_node((this.tree_handler.append_child)(from_hubbub_node(parent),
-                                                                      from_hubbub_node(child)));
+            *result = to_hubbub_node(node);
         }
         return ll::OK;
     }
 
     pub extern fn insert_before(ctx: *mut c_void, parent: *mut c_void, child: *mut c_void,
-                                result: *mut *mut c_void) -> ll::Error {
+                                reference: *mut c_void, result: *mut *mut c_void) -> ll::Error {
         debug!("ll insert before");
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
+        let hubbub_parent = from_hubbub_node(parent);
+        let parent = this.children_parent(hubbub_parent);
+        let child = from_hubbub_node(child);
+        let reference = from_hubbub_node(reference);
+        if this.attach_declarative_shadow_root(hubbub_parent, child) {
+            (this.tree_handler.ref_node)(child);
+            this.update_open_elements(hubbub_parent, child, reference);
+            unsafe { *result = to_hubbub_node(child); }
+            return ll::OK;
+        }
+        this.node_inserted(parent, child);
+        let node = (this.tree_handler.insert_before)(parent, child, reference);
+        this.update_open_elements(hubbub_parent, child, reference);
         unsafe {
-            *result = to_hubbub_node((this.tree_handler.insert_before)(from_hubbub_node(parent),
-                                                                       from_hubbub_node(child)));
+            *result = to_hubbub_node(node);
         }
         return ll::OK;
     }
@@ -396,8 +1361,9 @@ pub mod tree_callbacks {
 
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
+        let parent = this.children_parent(from_hubbub_node(parent));
         unsafe {
-            *result = to_hubbub_node((this.tree_handler.remove_child)(from_hubbub_node(parent),
+            *result = to_hubbub_node((this.tree_handler.remove_child)(parent,
                                                                       from_hubbub_node(child)));
         }
         return ll::OK;
@@ -409,8 +1375,17 @@ pub mod tree_callbacks {
 
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
+        let node = from_hubbub_node(node);
+        let clone = (this.tree_handler.clone_node)(node, deep);
+        // The tree builder only clones elements.
+        this.node_created(clone);
+        this.new_clones.insert(clone);
+        if this.template_nodes.contains(&node) {
+            this.template_nodes.insert(clone);
+            (this.tree_handler.ref_node)(clone);
+        }
         unsafe {
-            *result = to_hubbub_node((this.tree_handler.clone_node)(from_hubbub_node(node), deep));
+            *result = to_hubbub_node(clone);
         }
         return ll::OK;
     }
@@ -421,8 +1396,9 @@ pub mod tree_callbacks {
 
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
-        (this.tree_handler.reparent_children)(from_hubbub_node(node),
-                                              from_hubbub_node(new_parent));
+        let node = this.children_parent(from_hubbub_node(node));
+        let new_parent = this.children_parent(from_hubbub_node(new_parent));
+        (this.tree_handler.reparent_children)(node, new_parent);
         return ll::OK;
     }
 
@@ -445,8 +1421,9 @@ pub mod tree_callbacks {
 
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
+        let node = this.children_parent(from_hubbub_node(node));
         unsafe {
-            *result = (this.tree_handler.has_children)(from_hubbub_node(node));
+            *result = (this.tree_handler.has_children)(node);
         }
         return ll::OK;
     }
@@ -497,7 +1474,9 @@ pub mod tree_callbacks {
 
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
-        (this.tree_handler.complete_script)(from_hubbub_node(script));
+        let script = from_hubbub_node(script);
+        (this.tree_handler.complete_script)(script);
+        this.run_script(script);
         return ll::OK;
     }
 
@@ -506,7 +1485,39 @@ pub mod tree_callbacks {
 
         let self_opt: &mut Option<TreeHandlerPair> = unsafe { mem::transmute(ctx) };
         let this = self_opt.get_mut_ref();
-        (this.tree_handler.complete_style)(from_hubbub_node(style));
+        let style = from_hubbub_node(style);
+        (this.tree_handler.complete_style)(style);
+        this.style_completed(style);
+        return ll::OK;
+    }
+}
+
+pub mod token_callbacks {
+
+    use libc::c_void;
+    use std::mem;
+    use super::{TokenHandler, DoctypeToken, StartTagToken, EndTagToken, CommentToken};
+    use super::{CharacterToken, EofToken};
+    use super::tree_callbacks::{from_hubbub_string, from_hubbub_tag, from_hubbub_doctype};
+    use ll;
+
+    pub extern fn process_token(token: *const ll::Token, pw: *mut c_void) -> ll::Error {
+        debug!("ll process token");
+        let self_opt: &mut Option<&mut TokenHandler> = unsafe { mem::transmute(pw) };
+        let this = self_opt.get_mut_ref();
+        unsafe {
+            let data: *const u8 = &(*token).data as *const ll::Doctype as *const u8;
+            let token = match (*token).token_type {
+                0 => DoctypeToken(from_hubbub_doctype(mem::transmute(data))),
+                1 => StartTagToken(from_hubbub_tag(mem::transmute(data))),
+                2 => EndTagToken(from_hubbub_tag(mem::transmute(data))),
+                3 => CommentToken(from_hubbub_string(mem::transmute(data))),
+                4 => CharacterToken(from_hubbub_string(mem::transmute(data))),
+                5 => EofToken,
+                _ => fail!("unknown token type")
+            };
+            (this.process_token)(token);
+        }
         return ll::OK;
     }
 }
@@ -16,6 +16,23 @@ extern crate libc;
 #[phase(plugin, link)]
 extern crate log;
 
+pub mod dom;
+pub mod dump;
+pub mod forms;
+pub mod fragment;
 pub mod hubbub;
+pub mod links;
 pub mod ll;
+pub mod markdown;
+pub mod meta;
+pub mod rewrite;
+pub mod sanitize;
+pub mod select;
+pub mod serialize;
+pub mod stream;
+pub mod structured_data;
+pub mod tables;
+pub mod text;
+pub mod url;
 
+pub use fragment::parse_fragment;
@@ -0,0 +1,180 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// An inventory of the URLs a parsed document refers to: links, embedded resources, form
+// actions, and images in srcset attributes and inline styles.
+
+use std::ascii::StrAsciiExt;
+use dom::{Document, NodeId};
+use hubbub::{Attribute, Tag, NullNs, HtmlNs, SvgNs, XLinkNs, is_html_whitespace};
+use serialize::attribute_name;
+use url;
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Reference {
+    // The URL resolved against the base URL of the document, or as written if it can't be.
+    pub url: String,
+    // The URL as written in the attribute.
+    pub raw: String,
+    pub node: NodeId,
+    pub element: String,
+    // The qualified name of the attribute, such as "href" or "xlink:href".
+    pub attribute: String,
+}
+
+// The HTML elements and attributes that hold a single URL.
+static URL_ATTRIBUTES: &'static [(&'static str, &'static str)] = &[
+    ("a", "href"),
+    ("area", "href"),
+    ("audio", "src"),
+    ("embed", "src"),
+    ("form", "action"),
+    ("iframe", "src"),
+    ("img", "src"),
+    ("link", "href"),
+    ("script", "src"),
+    ("source", "src"),
+    ("track", "src"),
+    ("video", "src"),
+    ("video", "poster"),
+];
+
+// Lists the references in a document in tree order, resolved against the first `<base href>`
+// and `document_url`.
+pub fn references(document: &Document, document_url: &str) -> Vec<Reference> {
+    let base_url = url::document_base_url(document, document_url);
+    let mut references = Vec::new();
+    for node in document.descendants(document.root()) {
+        let tag = match document.tag(node) {
+            Some(tag) => tag,
+            None => continue
+        };
+        for attribute in tag.attributes.iter() {
+            let values = if is_url_attribute(tag, attribute) {
+                vec!(attribute.value.clone())
+            } else if attribute.ns == NullNs && attribute.name.as_slice() == "srcset" &&
+                      tag.ns == HtmlNs && (tag.name.as_slice() == "img" ||
+                                           tag.name.as_slice() == "source") {
+                srcset_urls(attribute.value.as_slice())
+            } else if attribute.ns == NullNs && attribute.name.as_slice() == "style" {
+                css_urls(attribute.value.as_slice())
+            } else {
+                continue
+            };
+            for raw in values.move_iter() {
+                let url = match url::resolve(base_url.as_slice(), raw.as_slice()) {
+                    Some(url) => url,
+                    None => url::clean_url(raw.as_slice())
+                };
+                references.push(Reference {
+                    url: url,
+                    raw: raw,
+                    node: node,
+                    element: tag.name.clone(),
+                    attribute: attribute_name(attribute),
+                });
+            }
+        }
+    }
+    references
+}
+
+fn is_url_attribute(tag: &Tag, attribute: &Attribute) -> bool {
+    match tag.ns {
+        HtmlNs => attribute.ns == NullNs && URL_ATTRIBUTES.iter().any(|&(element, name)| {
+            tag.name.as_slice() == element && attribute.name.as_slice() == name
+        }),
+        // SVG 2 allows a plain href in place of xlink:href.
+        SvgNs => attribute.name.as_slice() == "href" &&
+                 (attribute.ns == XLinkNs || attribute.ns == NullNs),
+        _ => false
+    }
+}
+
+// The URLs of the image candidates in a srcset attribute, such as "a.png 1x, b.png 2x".
+fn srcset_urls(srcset: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_left_chars(|c: char| c == ',' || is_html_whitespace(c));
+        if rest.is_empty() {
+            return urls
+        }
+        let end = rest.find(is_html_whitespace).unwrap_or(rest.len());
+        let candidate = rest.slice_to(end);
+        rest = rest.slice_from(end);
+        // A URL followed by a comma has no descriptors.
+        if candidate.ends_with(",") {
+            urls.push(candidate.trim_right_chars(',').to_string());
+            continue
+        }
+        urls.push(candidate.to_string());
+        // Skip the descriptors, which may contain commas inside parentheses.
+        let mut depth = 0u;
+        let mut end = rest.len();
+        for (index, c) in rest.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ',' if depth == 0 => {
+                    end = index;
+                    break
+                }
+                _ => {}
+            }
+        }
+        rest = rest.slice_from(end);
+    }
+}
+
+// The URLs in the `url()` functions of a declaration list.
+fn css_urls(style: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let lower = style.to_ascii_lower();
+    let mut position = 0;
+    loop {
+        let start = match lower.as_slice().slice_from(position).find_str("url(") {
+            Some(start) => position + start + "url(".len(),
+            None => return urls
+        };
+        let rest = style.slice_from(start).trim_left_chars(is_html_whitespace);
+        let (value, length) = if rest.starts_with("\"") || rest.starts_with("'") {
+            let quote = rest.char_at(0);
+            match rest.slice_from(1).find(quote) {
+                Some(end) => (rest.slice(1, end + 1), end + 2),
+                None => return urls
+            }
+        } else {
+            match rest.find(')') {
+                Some(end) => (rest.slice_to(end).trim_right_chars(is_html_whitespace), end + 1),
+                None => return urls
+            }
+        };
+        if !value.is_empty() {
+            urls.push(unescape_css(value));
+        }
+        position = style.len() - rest.len() + length;
+    }
+}
+
+// Removes the backslashes of CSS escapes of single characters, which is how quotes and
+// parentheses appear in URLs.
+fn unescape_css(value: &str) -> String {
+    let mut result = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+            continue
+        }
+        escaped = false;
+        result.push_char(c);
+    }
+    result
+}
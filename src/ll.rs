@@ -99,7 +99,8 @@ pub struct TreeHandler {
     pub ref_node: extern "C" fn(*mut c_void, *mut c_void) -> Error,
     pub unref_node: extern "C" fn(*mut c_void, *mut c_void) -> Error,
     pub append_child: extern "C" fn(*mut c_void, *mut c_void, *mut c_void, *mut *mut c_void) -> Error,
-    pub insert_before: extern "C" fn(*mut c_void, *mut c_void, *mut c_void, *mut *mut c_void) -> Error,
+    pub insert_before: extern "C" fn(*mut c_void, *mut c_void, *mut c_void, *mut c_void, *mut *mut c_void)
+                                     -> Error,
     pub remove_child: extern "C" fn(*mut c_void, *mut c_void, *mut c_void, *mut *mut c_void) -> Error,
     pub clone_node: extern "C" fn(*mut c_void, *mut c_void, bool, *mut *mut c_void) -> Error,
     pub reparent_children: extern "C" fn(*mut c_void, *mut c_void, *mut c_void) -> Error,
@@ -185,6 +186,8 @@ pub struct Tag {
 // Token data
 pub struct Token {
     pub token_type: TokenType,
-    pub data: u8,   // union: one of Doctype, Tag, Comment (string), or Character (string)
+    // union: one of Doctype, Tag, Comment (string), or Character (string). Doctype is the
+    // largest member, so it gives the union its size and alignment.
+    pub data: Doctype,
 }
 
@@ -0,0 +1,533 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Conversion of arena documents to CommonMark, with GitHub-flavored tables and strikethrough.
+//
+// Elements that only group their content, such as `div` or `span`, are replaced by their
+// content. Those that have no Markdown equivalent are handled as the caller's policy says.
+
+use std::cmp;
+use dom::{Document, NodeId, DocumentNode, DocumentFragmentNode, ShadowRootNode, TextNode};
+use dom::ElementNode;
+use hubbub::{Tag, HtmlNs};
+use serialize;
+use serialize::IncludeNode;
+use url::clean_url;
+
+// What to do with elements that have no Markdown equivalent.
+#[deriving(Clone, PartialEq, Show)]
+pub enum UnmappedPolicy {
+    // Keep them as inline HTML or HTML blocks.
+    KeepAsHtml,
+    // Drop their markup but convert their content.
+    KeepContent,
+    // Drop them along with their content.
+    DropContent,
+}
+
+// Elements that are dropped along with their content whatever the policy says.
+static DROPPED_ELEMENTS: &'static [&'static str] = &[
+    "head", "noscript", "script", "style", "template", "title",
+];
+
+// Block elements that only group their content.
+static CONTAINER_ELEMENTS: &'static [&'static str] = &[
+    "address", "article", "aside", "body", "center", "div", "figcaption", "figure", "footer",
+    "header", "hgroup", "html", "main", "nav", "p", "section",
+];
+
+// Inline elements that only group their content.
+static PHRASING_CONTAINER_ELEMENTS: &'static [&'static str] = &[
+    "abbr", "bdi", "bdo", "big", "data", "font", "label", "small", "span", "time",
+];
+
+// The other elements that break inline content.
+static BLOCK_ELEMENTS: &'static [&'static str] = &[
+    "blockquote", "caption", "dd", "details", "dialog", "dir", "dl", "dt", "fieldset", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "hr", "legend", "li", "listing", "menu", "ol",
+    "plaintext", "pre", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+    "xmp",
+];
+
+struct Converter<'a> {
+    document: &'a Document,
+    policy: UnmappedPolicy,
+}
+
+// Converts the children of `node` to Markdown.
+pub fn to_markdown(document: &Document, node: NodeId, policy: UnmappedPolicy) -> String {
+    let converter = Converter {
+        document: document,
+        policy: policy,
+    };
+    let mut markdown = converter.blocks(node).connect("\n\n");
+    if !markdown.is_empty() {
+        markdown.push_char('\n');
+    }
+    markdown
+}
+
+impl<'a> Converter<'a> {
+    fn is_block(&self, node: NodeId) -> bool {
+        self.document.tag(node).map_or(false, |tag| {
+            tag.is_html_element_in(CONTAINER_ELEMENTS) || tag.is_html_element_in(BLOCK_ELEMENTS)
+        })
+    }
+
+    // The Markdown blocks for the children of `node`, with runs of inline content as paragraphs.
+    fn blocks(&self, node: NodeId) -> Vec<String> {
+        let mut blocks = Vec::new();
+        let mut inline = String::new();
+        let node = self.document.template_contents(node).unwrap_or(node);
+        for child in self.document.children(node) {
+            if self.is_block(child) {
+                push_paragraph(&mut blocks, inline.as_slice());
+                inline = String::new();
+                self.block(child, &mut blocks);
+            } else {
+                self.inline(child, &mut inline);
+            }
+        }
+        push_paragraph(&mut blocks, inline.as_slice());
+        blocks
+    }
+
+    fn block(&self, node: NodeId, blocks: &mut Vec<String>) {
+        let tag = self.document.tag(node).unwrap();
+        let block = match tag.name.as_slice() {
+            _ if tag.is_html_element_in(CONTAINER_ELEMENTS) => {
+                blocks.extend(self.blocks(node).move_iter());
+                return
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let mut text = String::new();
+                self.inline_children(node, &mut text);
+                let text = trim_inline(text.as_slice()).replace("\\\n", " ");
+                if text.is_empty() {
+                    return
+                }
+                let level = tag.name.as_slice().char_at(1).to_digit(10).unwrap();
+                format!("{} {}", String::from_char(level, '#'), text)
+            }
+            "blockquote" => {
+                let content = self.blocks(node).connect("\n\n");
+                if content.is_empty() {
+                    return
+                }
+                indent_lines(content.as_slice(), "> ", "> ")
+            }
+            "pre" => self.code_block(node),
+            "ul" | "ol" => self.list(node, tag),
+            "hr" => "---".to_string(),
+            "table" => match self.table(node) {
+                Some(table) => table,
+                None => return self.unmapped_block(node, blocks)
+            },
+            _ => return self.unmapped_block(node, blocks)
+        };
+        if !block.is_empty() {
+            blocks.push(block);
+        }
+    }
+
+    fn unmapped_block(&self, node: NodeId, blocks: &mut Vec<String>) {
+        match self.policy {
+            KeepAsHtml => blocks.push(serialize::to_string(self.document, node, IncludeNode)),
+            KeepContent => blocks.extend(self.blocks(node).move_iter()),
+            DropContent => {}
+        }
+    }
+
+    fn code_block(&self, node: NodeId) -> String {
+        let text = self.document.text_content(node);
+        let text = text.as_slice().trim_right_chars('\n');
+        // The language is given by a class of a `code` child, as in `language-rust`.
+        let language = self.document.children(node).filter_map(|child| {
+            self.document.tag(child)
+        }).find(|tag| {
+            tag.ns == HtmlNs && tag.name.as_slice() == "code"
+        }).and_then(|tag| tag.get_attribute("class")).and_then(|class| {
+            class.split(|c: char| c.is_whitespace()).filter_map(|class| {
+                if class.starts_with("language-") {
+                    Some(class.slice_from(9))
+                } else if class.starts_with("lang-") {
+                    Some(class.slice_from(5))
+                } else {
+                    None
+                }
+            }).next()
+        }).unwrap_or("");
+        let fence = String::from_char(cmp::max(3, longest_run(text, '`') + 1), '`');
+        if text.is_empty() {
+            format!("{}{}\n{}", fence, language, fence)
+        } else {
+            format!("{}{}\n{}\n{}", fence, language, text, fence)
+        }
+    }
+
+    fn list(&self, node: NodeId, tag: &Tag) -> String {
+        let ordered = tag.name.as_slice() == "ol";
+        let mut number = tag.get_attribute("start").and_then(|start| {
+            from_str::<uint>(start.trim())
+        }).unwrap_or(1);
+        let mut items: Vec<String> = Vec::new();
+        for child in self.document.children(node) {
+            let is_item = self.document.tag(child).map_or(false, |tag| {
+                tag.ns == HtmlNs && tag.name.as_slice() == "li"
+            });
+            let content = if is_item {
+                self.list_item(child)
+            } else if self.is_block(child) {
+                let mut blocks = Vec::new();
+                self.block(child, &mut blocks);
+                blocks.connect("\n\n")
+            } else {
+                let mut text = String::new();
+                self.inline(child, &mut text);
+                escape_line_starts(trim_inline(text.as_slice()))
+            };
+            // Content other than list items, such as a nested list that should have been inside
+            // one, goes with the item before it.
+            if !is_item && !items.is_empty() {
+                if !content.is_empty() {
+                    let last = items.mut_last().unwrap();
+                    last.push_char('\n');
+                    last.push_str(indent_lines(content.as_slice(), "  ", "  ").as_slice());
+                }
+                continue
+            }
+            if !is_item && content.is_empty() {
+                continue
+            }
+            let marker = if ordered {
+                number += 1;
+                format!("{}. ", number - 1)
+            } else {
+                "- ".to_string()
+            };
+            let indent = String::from_char(marker.len(), ' ');
+            items.push(indent_lines(content.as_slice(), marker.as_slice(), indent.as_slice()));
+        }
+        // A list with blank lines in any of its items is loose, so all of them are separated by
+        // blank lines.
+        if items.iter().any(|item| item.as_slice().contains("\n\n")) {
+            items.connect("\n\n")
+        } else {
+            items.connect("\n")
+        }
+    }
+
+    fn list_item(&self, node: NodeId) -> String {
+        // Blocks other than lists only stay apart from what follows with a blank line between.
+        let loose = self.document.children(node).any(|child| {
+            self.is_block(child) && self.document.tag(child).map_or(false, |tag| {
+                tag.name.as_slice() != "ul" && tag.name.as_slice() != "ol"
+            })
+        });
+        self.blocks(node).connect(if loose { "\n\n" } else { "\n" })
+    }
+
+    // Tables with simple cells, each holding inline content and spanning a single row and column,
+    // become GitHub-flavored tables, with their first row as the header.
+    fn table(&self, node: NodeId) -> Option<String> {
+        let mut caption = None;
+        let mut rows = Vec::new();
+        for child in self.document.children(node) {
+            match self.document.tag(child) {
+                Some(tag) if tag.ns == HtmlNs => match tag.name.as_slice() {
+                    "caption" => {
+                        let mut text = String::new();
+                        self.inline_children(child, &mut text);
+                        caption = Some(escape_line_starts(trim_inline(text.as_slice())));
+                    }
+                    "tr" => rows.push(child),
+                    "thead" | "tbody" | "tfoot" => {
+                        rows.extend(self.document.children(child).filter(|&row| {
+                            self.document.tag(row).map_or(false, |tag| {
+                                tag.ns == HtmlNs && tag.name.as_slice() == "tr"
+                            })
+                        }))
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if rows.is_empty() {
+            return None
+        }
+
+        let mut grid: Vec<Vec<String>> = Vec::new();
+        for &row in rows.iter() {
+            let mut cells = Vec::new();
+            for cell in self.document.children(row) {
+                let tag = match self.document.tag(cell) {
+                    Some(tag) if tag.ns == HtmlNs && (tag.name.as_slice() == "td" ||
+                                                      tag.name.as_slice() == "th") => tag,
+                    _ => continue
+                };
+                let spans = ["colspan", "rowspan"].iter().any(|name| {
+                    tag.get_attribute(*name).map_or(false, |span| span.trim() != "1")
+                });
+                if spans || self.document.descendants(cell).any(|node| self.is_block(node)) {
+                    return None
+                }
+                let mut text = String::new();
+                self.inline_children(cell, &mut text);
+                let line_break = if self.policy == KeepAsHtml { "<br>" } else { " " };
+                cells.push(trim_inline(text.as_slice()).replace("\\\n", line_break));
+            }
+            grid.push(cells);
+        }
+
+        let columns = cmp::max(1, grid.iter().map(|cells| cells.len()).max().unwrap_or(0));
+        let mut lines = Vec::new();
+        for (index, cells) in grid.iter().enumerate() {
+            let mut line = "|".to_string();
+            for column in range(0, columns) {
+                line.push_char(' ');
+                if column < cells.len() {
+                    line.push_str(cells.get(column).as_slice());
+                }
+                line.push_str(" |");
+            }
+            lines.push(line);
+            if index == 0 {
+                let mut line = "|".to_string();
+                for _ in range(0, columns) {
+                    line.push_str(" --- |");
+                }
+                lines.push(line);
+            }
+        }
+        let table = lines.connect("\n");
+        match caption {
+            Some(ref caption) if !caption.is_empty() => Some(format!("{}\n\n{}", caption, table)),
+            _ => Some(table)
+        }
+    }
+
+    fn inline_children(&self, node: NodeId, output: &mut String) {
+        let node = self.document.template_contents(node).unwrap_or(node);
+        for child in self.document.children(node) {
+            self.inline(child, output);
+        }
+    }
+
+    fn inline(&self, node: NodeId, output: &mut String) {
+        match self.document.node(node).data {
+            TextNode(ref text) => push_text(output, text.as_slice()),
+            ElementNode(ref tag) => self.inline_element(node, tag, output),
+            DocumentNode | DocumentFragmentNode | ShadowRootNode(_) => {
+                self.inline_children(node, output)
+            }
+            _ => {}
+        }
+    }
+
+    fn inline_element(&self, node: NodeId, tag: &Tag, output: &mut String) {
+        if tag.ns != HtmlNs {
+            return self.unmapped_inline(node, output)
+        }
+        match tag.name.as_slice() {
+            _ if tag.is_html_element_in(DROPPED_ELEMENTS) => {}
+            "br" => output.push_str("\\\n"),
+            "em" | "i" => self.delimited(node, "*", "*", output),
+            "strong" | "b" => self.delimited(node, "**", "**", output),
+            "del" | "s" | "strike" => self.delimited(node, "~~", "~~", output),
+            "code" => push_code_span(output, self.document.text_content(node).as_slice()),
+            "a" => match tag.get_attribute("href") {
+                Some(href) => {
+                    let end = format!("]({})", destination(href, tag.get_attribute("title")));
+                    self.delimited(node, "[", end.as_slice(), output)
+                }
+                None => self.inline_children(node, output)
+            },
+            "img" => match tag.get_attribute("src") {
+                Some(src) => {
+                    let mut alt = String::new();
+                    push_text(&mut alt, tag.get_attribute("alt").unwrap_or(""));
+                    output.push_str(format!("![{}]({})", alt.as_slice().trim_chars(' '),
+                                            destination(src, tag.get_attribute("title")))
+                                    .as_slice());
+                }
+                None => {}
+            },
+            _ if tag.is_html_element_in(PHRASING_CONTAINER_ELEMENTS) ||
+                 tag.is_html_element_in(CONTAINER_ELEMENTS) => self.inline_children(node, output),
+            _ => self.unmapped_inline(node, output)
+        }
+    }
+
+    fn unmapped_inline(&self, node: NodeId, output: &mut String) {
+        match self.policy {
+            KeepAsHtml => {
+                output.push_str(serialize::to_string(self.document, node, IncludeNode).as_slice())
+            }
+            KeepContent => self.inline_children(node, output),
+            DropContent => {}
+        }
+    }
+
+    // Writes the content of `node` between `start` and `end`, moving the whitespace around it
+    // outside, where it doesn't keep the delimiters from being recognized.
+    fn delimited(&self, node: NodeId, start: &str, end: &str, output: &mut String) {
+        let mut content = String::new();
+        self.inline_children(node, &mut content);
+        let trimmed = trim_inline(content.as_slice());
+        if trimmed.is_empty() && start != "[" {
+            if !content.is_empty() {
+                push_text(output, " ");
+            }
+            return
+        }
+        if content.as_slice().starts_with(" ") {
+            push_text(output, " ");
+        }
+        output.push_str(start);
+        output.push_str(trimmed);
+        output.push_str(end);
+        if content.as_slice().ends_with(" ") {
+            output.push_char(' ');
+        }
+    }
+}
+
+fn push_paragraph(blocks: &mut Vec<String>, inline: &str) {
+    let paragraph = trim_inline(inline);
+    if !paragraph.is_empty() {
+        blocks.push(escape_line_starts(paragraph));
+    }
+}
+
+// Removes the spaces and hard line breaks around inline content.
+fn trim_inline<'a>(mut text: &'a str) -> &'a str {
+    loop {
+        text = text.trim_chars(' ');
+        if text.starts_with("\\\n") {
+            text = text.slice_from(2);
+        } else if text.ends_with("\\\n") {
+            text = text.slice_to(text.len() - 2);
+        } else {
+            return text
+        }
+    }
+}
+
+// Appends text with its whitespace collapsed and the characters that Markdown would interpret
+// escaped.
+fn push_text(output: &mut String, text: &str) {
+    let chars: Vec<char> = text.chars().collect();
+    for (index, &c) in chars.iter().enumerate() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' | '\x0c' => {
+                let collapsed = {
+                    let slice = output.as_slice();
+                    slice.ends_with(" ") || slice.ends_with("\n")
+                };
+                if !collapsed {
+                    output.push_char(' ');
+                }
+                continue
+            }
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~' => {
+                output.push_char('\\');
+            }
+            // Only what could be read as a character reference.
+            '&' if index + 1 < chars.len() && (chars.get(index + 1).is_alphanumeric() ||
+                                                *chars.get(index + 1) == '#') => {
+                output.push_char('\\');
+            }
+            _ => {}
+        }
+        output.push_char(c);
+    }
+}
+
+// Escapes what would start a list item, a thematic break or a setext heading underline at the
+// start of each line of a paragraph.
+fn escape_line_starts(paragraph: &str) -> String {
+    let lines: Vec<String> = paragraph.split('\n').map(|line| {
+        if line.starts_with("-") || line.starts_with("+") || line.starts_with("=") {
+            return format!("\\{}", line)
+        }
+        // Only ASCII digits make an ordered list marker, each a byte long.
+        let digits = line.chars().take_while(|&c| c >= '0' && c <= '9').count();
+        if digits > 0 && digits < line.len() {
+            let c = line.char_at(digits);
+            if c == '.' || c == ')' {
+                return format!("{}\\{}", line.slice_to(digits), line.slice_from(digits))
+            }
+        }
+        line.to_string()
+    }).collect();
+    lines.connect("\n")
+}
+
+fn push_code_span(output: &mut String, text: &str) {
+    let text = text.replace("\n", " ");
+    let text = text.as_slice();
+    if text.is_empty() {
+        return
+    }
+    let fence = String::from_char(longest_run(text, '`') + 1, '`');
+    // One space is stripped from each end of code spans that start with a backtick or have
+    // spaces at both ends.
+    let padded = text.starts_with("`") || text.ends_with("`") ||
+        (text.starts_with(" ") && text.ends_with(" ") && !text.chars().all(|c| c == ' '));
+    let padding = if padded { " " } else { "" };
+    output.push_str(format!("{}{}{}{}{}", fence, padding, text, padding, fence).as_slice());
+}
+
+fn longest_run(text: &str, c: char) -> uint {
+    let mut longest = 0;
+    let mut run = 0;
+    for other in text.chars() {
+        if other == c {
+            run += 1;
+            longest = cmp::max(longest, run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
+// A link destination, with its title if any.
+fn destination(url: &str, title: Option<&str>) -> String {
+    let url = clean_url(url);
+    let url = url.as_slice();
+    let mut destination = if url.is_empty() || url.chars().any(|c| {
+        c == ' ' || c == '(' || c == ')' || c == '<' || c == '>' || c < ' '
+    }) {
+        format!("<{}>", url.replace("<", "\\<").as_slice().replace(">", "\\>"))
+    } else {
+        url.to_string()
+    };
+    match title {
+        Some(title) => {
+            let title = title.replace("\\", "\\\\").as_slice().replace("\"", "\\\"");
+            destination.push_str(format!(" \"{}\"", title).as_slice());
+        }
+        None => {}
+    }
+    destination
+}
+
+fn indent_lines(text: &str, first: &str, rest: &str) -> String {
+    let lines: Vec<String> = text.split('\n').enumerate().map(|(index, line)| {
+        let prefix = if index == 0 { first } else { rest };
+        if line.is_empty() {
+            prefix.trim_right().to_string()
+        } else {
+            format!("{}{}", prefix, line)
+        }
+    }).collect();
+    lines.connect("\n")
+}
@@ -0,0 +1,256 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Extraction of page metadata, as used for link previews: the title, meta tags, Open Graph and
+// Twitter card properties, and links to the canonical URL, icons and alternate versions.
+
+use std::ascii::StrAsciiExt;
+use std::cell::RefCell;
+use dom;
+use dom::{Document, NodeId};
+use hubbub::{Parser, TreeObserver, Tag, HtmlNs, is_html_whitespace, observe_tree};
+
+// How many bytes are parsed at a time, between checks for the end of the head.
+static CHUNK_SIZE: uint = 1024;
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Link {
+    // The link types, lowercased.
+    pub rel: Vec<String>,
+    pub href: String,
+    pub link_type: Option<String>,
+    pub hreflang: Option<String>,
+    pub media: Option<String>,
+    pub sizes: Option<String>,
+    pub title: Option<String>,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub canonical: Option<String>,
+    pub icons: Vec<Link>,
+    pub alternates: Vec<Link>,
+    // Open Graph and Twitter card properties in document order, with their full names such as
+    // "og:image". Properties can repeat.
+    pub open_graph: Vec<(String, String)>,
+    pub twitter: Vec<(String, String)>,
+    pub lang: Option<String>,
+    pub charset: Option<String>,
+}
+
+struct BodyWatcher {
+    body_started: bool,
+}
+
+impl TreeObserver for BodyWatcher {
+    fn creating_element(&mut self, tag: &Tag) {
+        if tag.is_html_element_in(&["body", "frameset"]) {
+            self.body_started = true;
+        }
+    }
+}
+
+// Parses the head of a document and extracts its metadata. Parsing stops as soon as the body
+// starts, and the charset is the one the parser decoded the input as.
+pub fn extract(data: &[u8], encoding: &str) -> Metadata {
+    let document = RefCell::new(Document::new());
+    let body_watcher = RefCell::new(BodyWatcher { body_started: false });
+
+    let charset = dom::with_tree_handler(&document, |tree_handler| {
+        observe_tree(tree_handler, &body_watcher, |handler| {
+            let root = document.borrow().root().to_node_ptr();
+            let mut parser = Parser::new(encoding, false);
+            parser.set_tree_handler(handler);
+            parser.set_document_node(root);
+            for chunk in data.chunks(CHUNK_SIZE) {
+                parser.parse_chunk(chunk);
+                if body_watcher.borrow().body_started {
+                    break
+                }
+            }
+            if !body_watcher.borrow().body_started {
+                parser.completed();
+            }
+            let (charset, _) = parser.read_charset();
+            charset
+        })
+    });
+
+    let document = document.unwrap();
+    let mut metadata = from_document(&document, document.root());
+    if charset.is_some() {
+        metadata.charset = charset;
+    }
+    metadata
+}
+
+// Extracts the metadata of an already parsed document, looking at the descendants of `node`.
+// The charset is the one declared in the document, if any.
+pub fn from_document(document: &Document, node: NodeId) -> Metadata {
+    let mut metadata = Metadata {
+        title: None,
+        description: None,
+        keywords: Vec::new(),
+        canonical: None,
+        icons: Vec::new(),
+        alternates: Vec::new(),
+        open_graph: Vec::new(),
+        twitter: Vec::new(),
+        lang: None,
+        charset: None,
+    };
+    for node in document.descendants(node) {
+        let tag = match document.tag(node) {
+            Some(tag) if tag.ns == HtmlNs => tag,
+            _ => continue
+        };
+        match tag.name.as_slice() {
+            "html" if metadata.lang.is_none() => {
+                metadata.lang = tag.get_attribute("lang").map(|lang| trim(lang).to_string());
+            }
+            "title" if metadata.title.is_none() => {
+                metadata.title = Some(collapse_whitespace(document.text_content(node).as_slice()));
+            }
+            "meta" => metadata.meta(tag),
+            "link" => metadata.link(tag),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+fn trim<'a>(value: &'a str) -> &'a str {
+    value.trim_chars(is_html_whitespace)
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    let words: Vec<&str> = value.split(is_html_whitespace).filter(|word| {
+        !word.is_empty()
+    }).collect();
+    words.connect(" ")
+}
+
+impl Metadata {
+    fn meta(&mut self, tag: &Tag) {
+        // Meta elements holding Microdata properties describe items, not the page.
+        if tag.has_attribute("itemprop") {
+            return
+        }
+        match tag.get_attribute("charset") {
+            Some(charset) if self.charset.is_none() => {
+                self.charset = Some(trim(charset).to_string());
+            }
+            _ => {}
+        }
+        let content = match tag.get_attribute("content") {
+            Some(content) => content,
+            None => return
+        };
+        let http_equiv = tag.get_attribute("http-equiv").map_or(false, |http_equiv| {
+            trim(http_equiv).eq_ignore_ascii_case("content-type")
+        });
+        if http_equiv && self.charset.is_none() {
+            self.charset = charset_from_content_type(content);
+        }
+
+        // Open Graph uses the property attribute, but some pages use name, and some both.
+        let mut names: Vec<String> = Vec::new();
+        for attribute in ["property", "name"].iter() {
+            match tag.get_attribute(*attribute) {
+                Some(name) => {
+                    let name = trim(name).to_ascii_lower();
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                None => {}
+            }
+        }
+        for name in names.move_iter() {
+            match name.as_slice() {
+                "description" if self.description.is_none() => {
+                    self.description = Some(trim(content).to_string());
+                }
+                "keywords" if self.keywords.is_empty() => {
+                    self.keywords = content.split(',').map(|keyword| trim(keyword))
+                                           .filter(|keyword| !keyword.is_empty())
+                                           .map(|keyword| keyword.to_string()).collect();
+                }
+                _ if name.as_slice().starts_with("og:") => {
+                    self.open_graph.push((name.clone(), content.to_string()));
+                }
+                _ if name.as_slice().starts_with("twitter:") => {
+                    self.twitter.push((name.clone(), content.to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn link(&mut self, tag: &Tag) {
+        let href = match tag.get_attribute("href") {
+            Some(href) => trim(href).to_string(),
+            None => return
+        };
+        let rel: Vec<String> = match tag.get_attribute("rel") {
+            Some(rel) => rel.split(is_html_whitespace).filter(|token| !token.is_empty())
+                            .map(|token| token.to_ascii_lower()).collect(),
+            None => return
+        };
+        let has = |name: &str| rel.iter().any(|token| token.as_slice() == name);
+        if has("canonical") && self.canonical.is_none() {
+            self.canonical = Some(href.clone());
+        }
+        let icon = has("icon") || has("apple-touch-icon") || has("apple-touch-icon-precomposed");
+        // Alternate stylesheets are stylesheets, not alternate versions of the page.
+        let alternate = has("alternate") && !has("stylesheet");
+        if !icon && !alternate {
+            return
+        }
+        let attribute = |name: &str| tag.get_attribute(name).map(|value| value.to_string());
+        let link = Link {
+            rel: rel.clone(),
+            href: href,
+            link_type: attribute("type"),
+            hreflang: attribute("hreflang"),
+            media: attribute("media"),
+            sizes: attribute("sizes"),
+            title: attribute("title"),
+        };
+        if icon {
+            self.icons.push(link.clone());
+        }
+        if alternate {
+            self.alternates.push(link);
+        }
+    }
+}
+
+// The charset in a Content-Type value such as "text/html; charset=utf-8".
+fn charset_from_content_type(content: &str) -> Option<String> {
+    let lower = content.to_ascii_lower();
+    let start = match lower.as_slice().find_str("charset") {
+        Some(start) => start + "charset".len(),
+        None => return None
+    };
+    let rest = trim(content.slice_from(start));
+    if !rest.starts_with("=") {
+        return None
+    }
+    let value = trim(rest.slice_from(1)).trim_chars(|c: char| c == '"' || c == '\'');
+    let end = value.find(|c: char| c == ';' || is_html_whitespace(c)).unwrap_or(value.len());
+    if end == 0 {
+        None
+    } else {
+        Some(value.slice_to(end).to_string())
+    }
+}
@@ -0,0 +1,716 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Streaming rewriting of HTML with the tokenizer, without building a tree.
+//
+// Hubbub's tokens don't say where in the input they came from, so the input is fed to the
+// tokenizer up to the end of one tag at a time: the bytes fed since the last tag are then the
+// text before the tag followed by the tag itself, which are written out unchanged unless a
+// handler modified them. Markup the tokenizer drops, such as `</>`, is kept with the tag after
+// it.
+
+use std::ascii::StrAsciiExt;
+use std::cell::RefCell;
+use std::io;
+use std::io::{IoResult, MemWriter, Reader, Writer};
+use std::mem;
+use hubbub::{Parser, TokenHandler, Token, Tag, Attribute, NullNs, StartTagToken, EndTagToken};
+use hubbub::{CommentToken, CharacterToken, EofToken, ContentModel, PCData, CData};
+use hubbub::{is_html_whitespace, raw_text_content_model};
+use serialize::{write_escaped, is_void_element_name};
+
+// The elements whose start tags end an open paragraph.
+static PARAGRAPH_ENDING_ELEMENTS: &'static [&'static str] = &[
+    "address", "article", "aside", "blockquote", "center", "dd", "details", "dialog", "dir",
+    "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3",
+    "h4", "h5", "h6", "header", "hgroup", "hr", "li", "listing", "main", "menu", "nav", "ol", "p",
+    "plaintext", "pre", "search", "section", "summary", "table", "ul", "xmp",
+];
+
+// The elements that implied end tags don't reach past.
+static SCOPING_ELEMENTS: &'static [&'static str] = &[
+    "applet", "body", "button", "caption", "dl", "html", "marquee", "menu", "object", "ol",
+    "ruby", "select", "table", "tbody", "td", "template", "tfoot", "th", "thead", "tr", "ul",
+];
+
+// A compound selector of a type selector, `*`, `#id`, `.class`, `[attribute]` and
+// `[attribute=value]`, such as `a.external[href]`.
+pub struct Selector {
+    name: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Option<Selector> {
+        let mut result = Selector {
+            name: None,
+            id: None,
+            classes: Vec::new(),
+            attributes: Vec::new(),
+        };
+        let selector = selector.trim_chars(is_html_whitespace);
+        let mut rest = selector;
+        if rest.starts_with("*") {
+            rest = rest.slice_from(1);
+        } else {
+            let (name, after) = split_identifier(rest);
+            if !name.is_empty() {
+                result.name = Some(name.to_ascii_lower());
+            }
+            rest = after;
+        }
+        while !rest.is_empty() {
+            if rest.starts_with("#") || rest.starts_with(".") {
+                let (identifier, after) = split_identifier(rest.slice_from(1));
+                if identifier.is_empty() {
+                    return None
+                }
+                if rest.starts_with("#") {
+                    result.id = Some(identifier.to_string());
+                } else {
+                    result.classes.push(identifier.to_string());
+                }
+                rest = after;
+            } else if rest.starts_with("[") {
+                let end = match rest.find(']') {
+                    Some(end) => end,
+                    None => return None
+                };
+                let inner = rest.slice(1, end);
+                let (name, value) = match inner.find('=') {
+                    Some(index) => {
+                        let value = inner.slice_from(index + 1).trim_chars(is_html_whitespace);
+                        let value = if value.len() >= 2 &&
+                                (value.starts_with("\"") && value.ends_with("\"") ||
+                                 value.starts_with("'") && value.ends_with("'")) {
+                            value.slice(1, value.len() - 1)
+                        } else {
+                            value
+                        };
+                        (inner.slice_to(index), Some(value.to_string()))
+                    }
+                    None => (inner, None)
+                };
+                let name = name.trim_chars(is_html_whitespace).to_ascii_lower();
+                if name.is_empty() {
+                    return None
+                }
+                result.attributes.push((name, value));
+                rest = rest.slice_from(end + 1);
+            } else {
+                return None
+            }
+        }
+        Some(result)
+    }
+
+    pub fn matches(&self, tag: &Tag) -> bool {
+        match self.name {
+            Some(ref name) if !name.as_slice().eq_ignore_ascii_case(tag.name.as_slice()) => {
+                return false
+            }
+            _ => {}
+        }
+        match self.id {
+            Some(ref id) if tag.get_attribute("id") != Some(id.as_slice()) => return false,
+            _ => {}
+        }
+        let classes = tag.get_attribute("class").unwrap_or("");
+        for class in self.classes.iter() {
+            if !classes.split(is_html_whitespace).any(|c| c == class.as_slice()) {
+                return false
+            }
+        }
+        self.attributes.iter().all(|&(ref name, ref value)| {
+            match (tag.get_attribute(name.as_slice()), value) {
+                (Some(actual), &Some(ref value)) => actual == value.as_slice(),
+                (Some(_), &None) => true,
+                (None, _) => false,
+            }
+        })
+    }
+}
+
+fn split_identifier<'a>(input: &'a str) -> (&'a str, &'a str) {
+    let end = input.char_indices().find(|&(_, c)| {
+        !(c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii())
+    }).map_or(input.len(), |(index, _)| index);
+    (input.slice_to(end), input.slice_from(end))
+}
+
+// A start tag matched by a selector, along with the changes handlers asked for.
+pub struct Element {
+    tag: Tag,
+    modified: bool,
+    removed: bool,
+    replacement: String,
+    before: String,
+    after: String,
+    prepend: String,
+    append: String,
+    inner_content: Option<String>,
+}
+
+impl Element {
+    pub fn name<'a>(&'a self) -> &'a str {
+        self.tag.name.as_slice()
+    }
+
+    pub fn tag<'a>(&'a self) -> &'a Tag {
+        &self.tag
+    }
+
+    pub fn get_attribute<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.tag.get_attribute(name)
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.modified = true;
+        for attribute in self.tag.attributes.mut_iter() {
+            if attribute.ns == NullNs && attribute.name.as_slice() == name {
+                attribute.value = value.to_string();
+                return
+            }
+        }
+        self.tag.attributes.push(Attribute {
+            ns: NullNs,
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        self.modified = true;
+        self.tag.attributes.retain(|attribute| {
+            attribute.ns != NullNs || attribute.name.as_slice() != name
+        });
+    }
+
+    // Inserts markup before the start tag.
+    pub fn before(&mut self, markup: &str) {
+        self.before.push_str(markup);
+    }
+
+    // Inserts markup after the end tag, or after the start tag of a void element.
+    pub fn after(&mut self, markup: &str) {
+        self.after.push_str(markup);
+    }
+
+    // Inserts markup at the start of the element's content.
+    pub fn prepend(&mut self, markup: &str) {
+        self.prepend.push_str(markup);
+    }
+
+    // Inserts markup at the end of the element's content, before its end tag. If the end tag
+    // is omitted the markup goes where the element is implicitly closed by the end tag of an
+    // ancestor, by the body start tag for a head element, or at the end of the input.
+    pub fn append(&mut self, markup: &str) {
+        self.append.push_str(markup);
+    }
+
+    pub fn set_inner_content(&mut self, markup: &str) {
+        self.inner_content = Some(markup.to_string());
+    }
+
+    // Replaces the element and its content with markup.
+    pub fn replace(&mut self, markup: &str) {
+        self.removed = true;
+        self.replacement = markup.to_string();
+    }
+
+    pub fn remove(&mut self) {
+        self.replace("");
+    }
+}
+
+// A run of text between two tags. Text in script and style elements is raw text, which isn't
+// escaped.
+pub struct Text {
+    text: String,
+    raw_text: bool,
+    replacement: Option<String>,
+}
+
+impl Text {
+    pub fn as_str<'a>(&'a self) -> &'a str {
+        self.text.as_slice()
+    }
+
+    pub fn is_raw_text(&self) -> bool {
+        self.raw_text
+    }
+
+    pub fn replace(&mut self, text: &str) {
+        if self.raw_text {
+            self.replacement = Some(text.to_string());
+        } else {
+            let mut writer = MemWriter::new();
+            write_escaped(&mut writer, text, false).unwrap();
+            self.replacement = Some(String::from_utf8(writer.unwrap()).unwrap());
+        }
+    }
+
+    pub fn replace_with_markup(&mut self, markup: &str) {
+        self.replacement = Some(markup.to_string());
+    }
+
+    pub fn remove(&mut self) {
+        self.replacement = Some(String::new());
+    }
+}
+
+pub struct Comment {
+    text: String,
+    modified: bool,
+    removed: bool,
+}
+
+impl Comment {
+    pub fn as_str<'a>(&'a self) -> &'a str {
+        self.text.as_slice()
+    }
+
+    // Returns false, leaving the comment unchanged, if `text` can't be written inside a comment:
+    // "-->" or "--!>" would end it early, letting the rest of the text through as markup.
+    pub fn set_text(&mut self, text: &str) -> bool {
+        if !is_valid_comment_text(text) {
+            return false
+        }
+        self.text = text.to_string();
+        self.modified = true;
+        true
+    }
+
+    pub fn remove(&mut self) {
+        self.removed = true;
+    }
+}
+
+pub trait ElementHandler {
+    fn element(&mut self, element: &mut Element);
+}
+
+pub trait TextHandler {
+    fn text(&mut self, text: &mut Text);
+}
+
+pub trait CommentHandler {
+    fn comment(&mut self, comment: &mut Comment);
+}
+
+struct ElementHandlerEntry<'a> {
+    selector: Selector,
+    handler: &'a mut ElementHandler+'a,
+}
+
+// An element whose end tag the rewriter is waiting for, either to insert markup around it or
+// because its content is being dropped.
+struct OpenElement {
+    // Where the element is in the stack of open elements.
+    depth: uint,
+    skip_content: bool,
+    keep_end_tag: bool,
+    append: String,
+    after: String,
+}
+
+struct RewriteState {
+    // The input fed to the tokenizer since the last tag, and the text decoded from it so far.
+    pending: Vec<u8>,
+    text: String,
+    // The element whose raw text content is being tokenized.
+    raw_text: Option<String>,
+    // The names of all the elements open at this point, as far as the tags show.
+    open_names: Vec<String>,
+    open_elements: Vec<OpenElement>,
+}
+
+impl RewriteState {
+    fn skipping(&self) -> bool {
+        self.open_elements.last().map_or(false, |element| element.skip_content)
+    }
+}
+
+pub struct Rewriter<'a> {
+    element_handlers: Vec<ElementHandlerEntry<'a>>,
+    text_handlers: Vec<&'a mut TextHandler+'a>,
+    comment_handlers: Vec<&'a mut CommentHandler+'a>,
+}
+
+impl<'a> Rewriter<'a> {
+    pub fn new() -> Rewriter<'a> {
+        Rewriter {
+            element_handlers: Vec::new(),
+            text_handlers: Vec::new(),
+            comment_handlers: Vec::new(),
+        }
+    }
+
+    // Calls `handler` for the start tags matched by `selector`, an element name or a simple
+    // selector as described for `Selector`.
+    pub fn on_element(&mut self, selector: &str, handler: &'a mut ElementHandler+'a) {
+        let selector = match Selector::parse(selector) {
+            Some(selector) => selector,
+            None => fail!("invalid selector: {}", selector)
+        };
+        self.element_handlers.push(ElementHandlerEntry {
+            selector: selector,
+            handler: handler,
+        });
+    }
+
+    pub fn on_text(&mut self, handler: &'a mut TextHandler+'a) {
+        self.text_handlers.push(handler);
+    }
+
+    pub fn on_comment(&mut self, handler: &'a mut CommentHandler+'a) {
+        self.comment_handlers.push(handler);
+    }
+
+    // Rewrites UTF-8 markup from `input` into `output`, writing each part as soon as the tag
+    // after it has been read.
+    pub fn run(&mut self, input: &mut Reader, output: &mut Writer) -> IoResult<()> {
+        let tokens = RefCell::new(Vec::new());
+        let mut token_handler = TokenHandler {
+            process_token: |token| tokens.borrow_mut().push(token),
+        };
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_token_handler(&mut token_handler);
+
+        let mut state = RewriteState {
+            pending: Vec::new(),
+            text: String::new(),
+            raw_text: None,
+            open_names: Vec::new(),
+            open_elements: Vec::new(),
+        };
+        let mut buffer = [0u8, ..8192];
+        loop {
+            let length = match input.read(buffer) {
+                Ok(length) => length,
+                Err(ref error) if error.kind == io::EndOfFile => break,
+                Err(error) => return Err(error)
+            };
+            let data = buffer.slice_to(length);
+            let mut start = 0;
+            for (index, &byte) in data.iter().enumerate() {
+                if byte != '>' as u8 {
+                    continue
+                }
+                parser.parse_chunk(data.slice(start, index + 1));
+                state.pending.push_all(data.slice(start, index + 1));
+                start = index + 1;
+                let tokens = mem::replace(&mut *tokens.borrow_mut(), Vec::new());
+                match try!(self.process_tokens(&mut state, tokens, output)) {
+                    Some(content_model) => parser.set_content_model(content_model),
+                    None => {}
+                }
+            }
+            parser.parse_chunk(data.slice_from(start));
+            state.pending.push_all(data.slice_from(start));
+        }
+        parser.completed();
+        let tokens = mem::replace(&mut *tokens.borrow_mut(), Vec::new());
+        try!(self.process_tokens(&mut state, tokens, output));
+
+        // Whatever is left is text, or a tag the input ended in the middle of.
+        let pending = mem::replace(&mut state.pending, Vec::new());
+        let text = mem::replace(&mut state.text, String::new());
+        try!(self.text(&mut state, pending.as_slice(), text, output));
+        try!(close_elements(&mut state, 0, &[], output));
+        output.flush()
+    }
+
+    // Handles the tokens produced by one chunk of input. Returns the content model to switch the
+    // tokenizer to, if it changes.
+    fn process_tokens(&mut self, state: &mut RewriteState, tokens: Vec<Token>,
+                      output: &mut Writer) -> IoResult<Option<ContentModel>> {
+        let mut markup = None;
+        for token in tokens.move_iter() {
+            match token {
+                CharacterToken(data) => state.text.push_str(data.as_slice()),
+                EofToken => {}
+                token => markup = Some(token),
+            }
+        }
+        let token = match markup {
+            Some(token) => token,
+            None => return Ok(None)
+        };
+
+        let pending = mem::replace(&mut state.pending, Vec::new());
+        let text = mem::replace(&mut state.text, String::new());
+        let start = markup_start(pending.as_slice(), state.raw_text.is_some());
+        try!(self.text(state, pending.slice_to(start), text, output));
+        let raw = pending.slice_from(start);
+        match token {
+            StartTagToken(tag) => self.start_tag(state, *tag, raw, output),
+            EndTagToken(tag) => self.end_tag(state, tag.name.as_slice(), raw, output),
+            CommentToken(data) => {
+                try!(self.comment(state, data, raw, output));
+                Ok(None)
+            }
+            _ => {
+                if !state.skipping() {
+                    try!(output.write(raw));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    fn text(&mut self, state: &mut RewriteState, raw: &[u8], text: String, output: &mut Writer)
+            -> IoResult<()> {
+        if raw.is_empty() || state.skipping() {
+            return Ok(())
+        }
+        if self.text_handlers.is_empty() {
+            return output.write(raw)
+        }
+        let raw_text = match state.raw_text {
+            Some(ref name) => raw_text_content_model(name.as_slice()) == Some(CData),
+            None => false
+        };
+        let mut text = Text {
+            text: text,
+            raw_text: raw_text,
+            replacement: None,
+        };
+        for handler in self.text_handlers.mut_iter() {
+            handler.text(&mut text);
+        }
+        match text.replacement {
+            Some(replacement) => output.write_str(replacement.as_slice()),
+            None => output.write(raw)
+        }
+    }
+
+    fn comment(&mut self, state: &mut RewriteState, data: String, raw: &[u8],
+               output: &mut Writer) -> IoResult<()> {
+        if state.skipping() {
+            return Ok(())
+        }
+        let mut comment = Comment {
+            text: data,
+            modified: false,
+            removed: false,
+        };
+        for handler in self.comment_handlers.mut_iter() {
+            handler.comment(&mut comment);
+        }
+        if comment.removed {
+            Ok(())
+        } else if comment.modified {
+            write!(output, "<!--{}-->", comment.text)
+        } else {
+            output.write(raw)
+        }
+    }
+
+    fn start_tag(&mut self, state: &mut RewriteState, tag: Tag, raw: &[u8], output: &mut Writer)
+                 -> IoResult<Option<ContentModel>> {
+        let void = is_void_element_name(tag.name.as_slice());
+        let content_model = if void { None } else { raw_text_content_model(tag.name.as_slice()) };
+        match content_model {
+            Some(PCData) | None => {}
+            Some(_) => state.raw_text = Some(tag.name.clone()),
+        }
+
+        match implied_end(state.open_names.as_slice(), tag.name.as_slice()) {
+            Some(index) => try!(close_elements(state, index, &[], output)),
+            None => {}
+        }
+        let depth = state.open_names.len();
+        if !void {
+            state.open_names.push(tag.name.clone());
+        }
+        if state.skipping() {
+            return Ok(content_model)
+        }
+
+        let mut element = Element {
+            tag: tag,
+            modified: false,
+            removed: false,
+            replacement: String::new(),
+            before: String::new(),
+            after: String::new(),
+            prepend: String::new(),
+            append: String::new(),
+            inner_content: None,
+        };
+        for entry in self.element_handlers.mut_iter() {
+            if entry.selector.matches(&element.tag) {
+                entry.handler.element(&mut element);
+            }
+        }
+
+        try!(output.write_str(element.before.as_slice()));
+        if element.removed {
+            try!(output.write_str(element.replacement.as_slice()));
+            if void {
+                try!(output.write_str(element.after.as_slice()));
+            } else {
+                state.open_elements.push(OpenElement {
+                    depth: depth,
+                    skip_content: true,
+                    keep_end_tag: false,
+                    append: String::new(),
+                    after: element.after,
+                });
+            }
+            return Ok(content_model)
+        }
+
+        if element.modified {
+            try!(write_start_tag(output, &element.tag));
+        } else {
+            try!(output.write(raw));
+        }
+        try!(output.write_str(element.prepend.as_slice()));
+        if void {
+            try!(output.write_str(element.append.as_slice()));
+            try!(output.write_str(element.after.as_slice()));
+            return Ok(content_model)
+        }
+        let skip_content = match element.inner_content {
+            Some(ref markup) => {
+                try!(output.write_str(markup.as_slice()));
+                true
+            }
+            None => false
+        };
+        if skip_content || !element.append.is_empty() || !element.after.is_empty() {
+            state.open_elements.push(OpenElement {
+                depth: depth,
+                skip_content: skip_content,
+                keep_end_tag: true,
+                append: element.append,
+                after: element.after,
+            });
+        }
+        Ok(content_model)
+    }
+
+    fn end_tag(&mut self, state: &mut RewriteState, name: &str, raw: &[u8], output: &mut Writer)
+               -> IoResult<Option<ContentModel>> {
+        let mut content_model = None;
+        if state.raw_text.as_ref().map_or(false, |raw_text| raw_text.as_slice() == name) {
+            state.raw_text = None;
+            content_model = Some(PCData);
+        }
+
+        let index = match state.open_names.iter().rposition(|open| open.as_slice() == name) {
+            Some(index) => index,
+            None => {
+                if !state.skipping() {
+                    try!(output.write(raw));
+                }
+                return Ok(content_model)
+            }
+        };
+        // Elements opened inside this one whose end tags were omitted are closed by this one.
+        let tracked = state.open_elements.iter().any(|element| element.depth == index);
+        try!(close_elements(state, index, raw, output));
+        if !tracked && !state.skipping() {
+            try!(output.write(raw));
+        }
+        Ok(content_model)
+    }
+}
+
+// Closes the elements from `depth` on in the stack of open elements, writing out the markup
+// added around the ones being tracked and `end_tag` for the one at `depth` if it is kept.
+fn close_elements(state: &mut RewriteState, depth: uint, end_tag: &[u8], output: &mut Writer)
+                  -> IoResult<()> {
+    state.open_names.truncate(depth);
+    while state.open_elements.last().map_or(false, |element| element.depth >= depth) {
+        let element = state.open_elements.pop().unwrap();
+        try!(output.write_str(element.append.as_slice()));
+        if element.depth == depth && element.keep_end_tag {
+            try!(output.write(end_tag));
+        }
+        try!(output.write_str(element.after.as_slice()));
+    }
+    Ok(())
+}
+
+// Where the start tag `name` implicitly ends the open elements, if it does: at the outermost of
+// the elements whose end tag can be omitted before it, such as a paragraph before a block or a
+// list item before the next one, that isn't cut off by an element scoping it, such as a list.
+fn implied_end(open_names: &[String], name: &str) -> Option<uint> {
+    let mut end = None;
+    for (index, open) in open_names.iter().enumerate().rev() {
+        let open = open.as_slice();
+        if is_ended_by(open, name) {
+            end = Some(index);
+        } else if SCOPING_ELEMENTS.iter().any(|scoping| open == *scoping) {
+            break
+        }
+    }
+    end
+}
+
+// Whether the start tag `name` implies the end tag of an open element named `open`.
+// Whether `text` parses back as the data of a comment it is written in, as the HTML syntax
+// requires of comments.
+fn is_valid_comment_text(text: &str) -> bool {
+    !text.starts_with(">") && !text.starts_with("->") && !text.contains("<!--") &&
+        !text.contains("-->") && !text.contains("--!>") && !text.ends_with("<!-")
+}
+
+fn is_ended_by(open: &str, name: &str) -> bool {
+    let any = |names: &[&str]| names.iter().any(|other| name == *other);
+    match open {
+        "p" => any(PARAGRAPH_ENDING_ELEMENTS),
+        "li" => name == "li",
+        "dt" | "dd" => any(&["dt", "dd"]),
+        "option" => any(&["option", "optgroup"]),
+        "optgroup" => name == "optgroup",
+        "rt" | "rp" => any(&["rt", "rp"]),
+        "td" | "th" => any(&["td", "th", "tr", "tbody", "thead", "tfoot"]),
+        "tr" => any(&["tr", "tbody", "thead", "tfoot"]),
+        "tbody" | "thead" | "tfoot" => any(&["tbody", "thead", "tfoot"]),
+        "head" => any(&["body", "frameset"]),
+        _ => false
+    }
+}
+
+// Where the tag the tokenizer just emitted starts among the bytes fed since the previous one.
+// Outside of raw text that is the first `<` that can start markup, since any earlier tag would
+// have been emitted already; in raw text it is the start of the end tag that closed it.
+fn markup_start(pending: &[u8], raw_text: bool) -> uint {
+    let lt = '<' as u8;
+    if raw_text {
+        return range(0, pending.len()).rev().find(|&index| {
+            pending[index] == lt && index + 1 < pending.len() && pending[index + 1] == '/' as u8
+        }).unwrap_or(0)
+    }
+    range(0, pending.len()).find(|&index| {
+        pending[index] == lt && index + 1 < pending.len() && {
+            let next = pending[index + 1] as char;
+            next.is_alphabetic() && next.is_ascii() || next == '/' || next == '!' || next == '?'
+        }
+    }).unwrap_or(0)
+}
+
+fn write_start_tag(output: &mut Writer, tag: &Tag) -> IoResult<()> {
+    try!(write!(output, "<{}", tag.name));
+    for attribute in tag.attributes.iter() {
+        try!(write!(output, " {}=\"", attribute.name));
+        try!(write_escaped(output, attribute.value.as_slice(), true));
+        try!(output.write_str("\""));
+    }
+    if tag.self_closing {
+        try!(output.write_str(" /"));
+    }
+    output.write_str(">")
+}
@@ -0,0 +1,269 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Allowlist-based sanitizing of untrusted HTML.
+//
+// The markup is parsed into a tree exactly as a browser would parse it, and a new tree holding
+// only what the policy allows is serialized from it, so the output contains no markup that the
+// policy didn't let through. The result is parsed and sanitized again until it comes out
+// unchanged, which guards against markup that parses differently once serialized.
+
+use std::ascii::StrAsciiExt;
+use std::collections::{HashMap, HashSet};
+use dom;
+use dom::{Document, NodeId, DocumentNode, DocumentFragmentNode, ShadowRootNode, DoctypeNode};
+use dom::{CommentNode, TextNode, ElementNode};
+use hubbub::{Tag, Attribute, Ns, HtmlNs, SvgNs, MathMlNs};
+use serialize;
+use serialize::{attribute_name, ChildrenOnly};
+use url::clean_url;
+
+// Elements that are removed along with their content whatever the policy says: scripts and
+// styles, and the elements whose content is raw text in some parsing contexts, such as
+// `noscript` with scripting enabled, where markup kept in their attributes or text would be
+// parsed differently.
+static REMOVED_ELEMENTS: &'static [&'static str] = &[
+    "iframe", "noembed", "noframes", "noscript", "plaintext", "script", "style", "xmp",
+];
+
+// Elements whose content is removed along with them when they aren't allowed, rather than kept
+// in their place.
+static OPAQUE_ELEMENTS: &'static [&'static str] = &["object", "template", "textarea", "title"];
+
+// How many times the output is parsed again before giving up on it becoming stable.
+static MAX_PASSES: uint = 4;
+
+pub struct Policy {
+    elements: HashSet<(Ns, String)>,
+    // The attributes allowed on each element name, with "*" for all elements.
+    attributes: HashMap<String, HashSet<String>>,
+    url_attributes: HashSet<String>,
+    url_schemes: HashSet<String>,
+    allow_comments: bool,
+}
+
+impl Policy {
+    // A policy that allows nothing but text.
+    pub fn new() -> Policy {
+        let mut policy = Policy {
+            elements: HashSet::new(),
+            attributes: HashMap::new(),
+            url_attributes: HashSet::new(),
+            url_schemes: HashSet::new(),
+            allow_comments: false,
+        };
+        for name in ["href", "src", "xlink:href", "action", "formaction", "cite", "poster",
+                     "background"].iter() {
+            policy.url_attributes.insert(name.to_string());
+        }
+        policy
+    }
+
+    // A policy for user-submitted rich text: formatting, lists, tables, links and images over
+    // http, https and mailto.
+    pub fn basic() -> Policy {
+        let mut policy = Policy::new();
+        for name in ["a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "dd", "del",
+                     "div", "dl", "dt", "em", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i",
+                     "img", "ins", "kbd", "li", "ol", "p", "pre", "q", "s", "small", "span",
+                     "strong", "sub", "sup", "table", "tbody", "td", "tfoot", "th", "thead",
+                     "tr", "u", "ul"].iter() {
+            policy.allow_element(HtmlNs, *name);
+        }
+        policy.allow_attribute("*", "title");
+        policy.allow_attribute("*", "lang");
+        policy.allow_attribute("*", "dir");
+        policy.allow_attribute("a", "href");
+        policy.allow_attribute("img", "src");
+        policy.allow_attribute("img", "alt");
+        policy.allow_attribute("img", "width");
+        policy.allow_attribute("img", "height");
+        policy.allow_attribute("td", "colspan");
+        policy.allow_attribute("td", "rowspan");
+        policy.allow_attribute("th", "colspan");
+        policy.allow_attribute("th", "rowspan");
+        policy.allow_attribute("blockquote", "cite");
+        policy.allow_attribute("q", "cite");
+        policy.allow_url_scheme("http");
+        policy.allow_url_scheme("https");
+        policy.allow_url_scheme("mailto");
+        policy
+    }
+
+    pub fn allow_element(&mut self, ns: Ns, name: &str) {
+        self.elements.insert((ns, name.to_string()));
+    }
+
+    // Allows an attribute, by its qualified name such as "xlink:href", on elements named
+    // `element`, or on all elements if `element` is "*". Names are compared case-insensitively
+    // on HTML elements only, so attributes of foreign elements are given with their case, such
+    // as "viewBox". Event handler attributes are never allowed.
+    pub fn allow_attribute(&mut self, element: &str, attribute: &str) {
+        self.attributes.find_or_insert_with(element.to_string(), |_| HashSet::new())
+                       .insert(attribute.to_string());
+    }
+
+    // Marks an attribute as holding a URL, which is only kept if it is relative or its scheme
+    // is allowed.
+    pub fn add_url_attribute(&mut self, attribute: &str) {
+        self.url_attributes.insert(attribute.to_ascii_lower());
+    }
+
+    pub fn allow_url_scheme(&mut self, scheme: &str) {
+        self.url_schemes.insert(scheme.to_ascii_lower());
+    }
+
+    pub fn allow_comments(&mut self, allow: bool) {
+        self.allow_comments = allow;
+    }
+
+    fn allows_element(&self, tag: &Tag) -> bool {
+        self.elements.contains(&(tag.ns, tag.name.clone()))
+    }
+
+    fn allows_attribute(&self, tag: &Tag, attribute: &Attribute) -> bool {
+        let name = attribute_name(attribute);
+        if name.as_slice().len() > 2 && name.as_slice().slice_to(2).eq_ignore_ascii_case("on") {
+            return false
+        }
+        let allowed = |element: &str| {
+            self.attributes.find(&element.to_string()).map_or(false, |names| {
+                if tag.ns == HtmlNs {
+                    names.iter().any(|allowed| {
+                        allowed.as_slice().eq_ignore_ascii_case(name.as_slice())
+                    })
+                } else {
+                    names.contains(&name)
+                }
+            })
+        };
+        if !allowed(tag.name.as_slice()) && !allowed("*") {
+            return false
+        }
+        !self.url_attributes.contains(&name) || self.allows_url(attribute.value.as_slice())
+    }
+
+    fn allows_url(&self, value: &str) -> bool {
+        let value = clean_url(value);
+        let value = value.as_slice().trim_left_chars(|c: char| c <= ' ');
+        let end = match value.find(|c: char| c == ':' || c == '/' || c == '?' || c == '#') {
+            Some(end) if value.char_at(end) == ':' => end,
+            // No scheme, so the URL is relative.
+            _ => return true
+        };
+        let scheme = value.slice_to(end).to_ascii_lower();
+        self.url_schemes.contains(&scheme)
+    }
+}
+
+// Sanitizes `html` as the contents of a body element, returning the serialized result.
+pub fn sanitize(html: &str, policy: &Policy) -> String {
+    let context = Tag {
+        ns: HtmlNs,
+        name: "body".to_string(),
+        attributes: Vec::new(),
+        self_closing: false,
+    };
+    let mut html = html.to_string();
+    for _ in range(0, MAX_PASSES) {
+        let document = dom::parse_fragment(&context, html.as_bytes());
+        let sanitized = sanitize_document(&document, document.root(), policy);
+        let output = serialize::to_string(&sanitized, sanitized.root(), ChildrenOnly);
+        if output == html {
+            return output
+        }
+        html = output;
+    }
+    // Markup that keeps changing is reduced to its text.
+    let document = dom::parse_fragment(&context, html.as_bytes());
+    let mut text = Document::new();
+    let root = text.root();
+    let node = text.create_node(TextNode(document.text_content(document.root())));
+    text.append(root, node);
+    serialize::to_string(&text, root, ChildrenOnly)
+}
+
+// Builds a new document holding the children of `node` that `policy` allows.
+pub fn sanitize_document(document: &Document, node: NodeId, policy: &Policy) -> Document {
+    let mut sanitized = Document::new();
+    let root = sanitized.root();
+    copy_children(document, node, &mut sanitized, root, policy);
+    sanitized
+}
+
+fn copy_children(document: &Document, node: NodeId, sanitized: &mut Document, parent: NodeId,
+                 policy: &Policy) {
+    for child in document.children(node) {
+        copy_node(document, child, sanitized, parent, policy);
+    }
+}
+
+fn copy_node(document: &Document, node: NodeId, sanitized: &mut Document, parent: NodeId,
+             policy: &Policy) {
+    match document.node(node).data {
+        DocumentNode | DocumentFragmentNode | ShadowRootNode(_) | DoctypeNode(_) => {}
+        CommentNode(ref data) => {
+            // A comment can't contain "-->" once parsed, but "--!>" also ends one.
+            if policy.allow_comments && !data.as_slice().contains("--!>") {
+                let comment = sanitized.create_node(CommentNode(data.clone()));
+                sanitized.append(parent, comment);
+            }
+        }
+        TextNode(ref data) => {
+            let text = sanitized.create_node(TextNode(data.clone()));
+            sanitized.append(parent, text);
+        }
+        ElementNode(ref tag) => {
+            if REMOVED_ELEMENTS.iter().any(|name| tag.name.as_slice() == *name) {
+                return
+            }
+            if !policy.allows_element(tag) {
+                // The content of foreign elements only makes sense inside them, except where it
+                // is parsed as HTML.
+                let opaque = tag.ns != HtmlNs ||
+                    OPAQUE_ELEMENTS.iter().any(|name| tag.name.as_slice() == *name);
+                if !opaque || is_foreign_integration_point(tag) {
+                    copy_children(document, node, sanitized, parent, policy);
+                }
+                return
+            }
+            let attributes = tag.attributes.iter().filter(|attribute| {
+                policy.allows_attribute(tag, *attribute)
+            }).map(|attribute| attribute.clone()).collect();
+            let element = sanitized.create_node(ElementNode(Tag {
+                ns: tag.ns,
+                name: tag.name.clone(),
+                attributes: attributes,
+                self_closing: false,
+            }));
+            sanitized.append(parent, element);
+            copy_children(document, node, sanitized, element, policy);
+            match document.template_contents(node) {
+                Some(contents) => {
+                    let sanitized_contents = sanitized.ensure_template_contents(element);
+                    copy_children(document, contents, sanitized, sanitized_contents, policy);
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+// Foreign elements whose content is parsed as HTML, which can be kept in place of them.
+fn is_foreign_integration_point(tag: &Tag) -> bool {
+    match tag.ns {
+        SvgNs => ["foreignObject", "desc", "title"].iter().any(|name| {
+            tag.name.as_slice() == *name
+        }),
+        MathMlNs => ["mi", "mo", "mn", "ms", "mtext"].iter().any(|name| {
+            tag.name.as_slice() == *name
+        }),
+        _ => false
+    }
+}
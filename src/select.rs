@@ -0,0 +1,971 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// CSS selectors, as in Selectors Level 3 plus `:not()` with complex selectors, `:has()` and
+// `:nth-child(An+B of S)`, matched against arena documents.
+//
+// Namespace prefixes are fixed rather than declared with `@namespace`: "html", "svg", "math",
+// "xlink", "xml" and "xmlns". A type selector without a prefix matches elements in any
+// namespace. Dynamic pseudo-classes such as `:hover` and pseudo-elements never match.
+
+use std::ascii::StrAsciiExt;
+use std::char;
+use std::num;
+use dom::{Document, NodeId, DocumentNode, CommentNode, DoctypeNode};
+use hubbub::{Tag, Attribute, Ns, NullNs, HtmlNs, MathMlNs, SvgNs, XLinkNs, XmlNs, XmlNsNs};
+use hubbub::is_html_whitespace;
+
+type ParseResult<T> = Result<T, ()>;
+
+pub struct SelectorList {
+    selectors: Vec<ComplexSelector>,
+}
+
+// Compound selectors from left to right, each with the combinator that relates it to the one
+// before it.
+struct ComplexSelector {
+    compounds: Vec<(Combinator, Vec<SimpleSelector>)>,
+}
+
+#[deriving(Clone, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+enum NamespaceConstraint {
+    AnyNs,
+    SomeNs(Ns),
+}
+
+enum AttributeOperator {
+    Exists,
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+struct AttributeSelector {
+    ns: NamespaceConstraint,
+    name: String,
+    operator: AttributeOperator,
+    value: String,
+    case_insensitive: bool,
+}
+
+// An+B, optionally restricted to the siblings that match a selector list.
+struct Nth {
+    a: int,
+    b: int,
+    of: Option<SelectorList>,
+    from_end: bool,
+    of_type: bool,
+}
+
+enum SimpleSelector {
+    // The name as written, which foreign element names are compared with, and lowercased.
+    TypeSelector(NamespaceConstraint, String, String),
+    UniversalSelector(NamespaceConstraint),
+    IdSelector(String),
+    ClassSelector(String),
+    AttributeSelectorOf(AttributeSelector),
+    NthSelector(Nth),
+    NotSelector(SelectorList),
+    HasSelector(SelectorList),
+    LangSelector(String),
+    RootSelector,
+    EmptySelector,
+    LinkSelector,
+    CheckedSelector,
+    DisabledSelector,
+    EnabledSelector,
+    // Matches the element a relative selector in `:has()` is anchored to.
+    ScopeSelector,
+    NeverSelector,
+}
+
+impl SelectorList {
+    pub fn matches(&self, document: &Document, node: NodeId) -> bool {
+        document.tag(node).is_some() && self.matches_scoped(document, node, None)
+    }
+
+    // Whether `node` might match once the rest of the document has been parsed: only its last
+    // compound selector is checked, and the pseudo-classes that depend on its content or on
+    // later siblings are assumed to match.
+    pub fn could_match(&self, document: &Document, node: NodeId) -> bool {
+        document.tag(node).is_some() && self.selectors.iter().any(|selector| {
+            let &(_, ref compound) = selector.compounds.last().unwrap();
+            could_match_compound(compound.as_slice(), document, node)
+        })
+    }
+
+    // Whether `node` might match, once the rest of the document has been parsed, one of the
+    // compound selectors that look at the descendants of the element they match.
+    pub fn could_match_by_descendants(&self, document: &Document, node: NodeId) -> bool {
+        document.tag(node).is_some() && self.selectors.iter().any(|selector| {
+            selector.compounds.iter().any(|&(_, ref compound)| {
+                compound.iter().any(|simple| simple.depends_on_descendants()) &&
+                    could_match_compound(compound.as_slice(), document, node)
+            })
+        })
+    }
+
+    // Whether matching the list looks at the descendants of elements, through `:has()` or
+    // `:empty`.
+    pub fn depends_on_descendants(&self) -> bool {
+        self.selectors.iter().any(|selector| {
+            selector.compounds.iter().any(|&(_, ref compound)| {
+                compound.iter().any(|simple| simple.depends_on_descendants())
+            })
+        })
+    }
+
+    // Whether matching the list looks at the earlier siblings of elements, through a sibling
+    // combinator or a pseudo-class such as `:first-child`.
+    pub fn depends_on_siblings(&self) -> bool {
+        self.selectors.iter().any(|selector| {
+            selector.compounds.iter().any(|&(ref combinator, ref compound)| {
+                *combinator == NextSibling || *combinator == SubsequentSibling ||
+                    compound.iter().any(|simple| simple.depends_on_siblings())
+            })
+        })
+    }
+
+    fn matches_scoped(&self, document: &Document, node: NodeId, scope: Option<NodeId>) -> bool {
+        self.selectors.iter().any(|selector| selector.matches(document, node, scope))
+    }
+}
+
+// Parses a comma-separated list of selectors. Returns None if it is invalid.
+pub fn parse(selectors: &str) -> Option<SelectorList> {
+    let mut parser = SelectorParser {
+        input: selectors.chars().collect(),
+        position: 0,
+    };
+    parser.skip_whitespace();
+    let list = match parser.selector_list(false) {
+        Ok(list) => list,
+        Err(()) => return None
+    };
+    if parser.peek().is_some() {
+        return None
+    }
+    Some(list)
+}
+
+// The elements below `root` that match `selectors`, in document order.
+pub fn select(document: &Document, root: NodeId, selectors: &SelectorList) -> Vec<NodeId> {
+    document.descendants(root).filter(|&node| selectors.matches(document, node)).collect()
+}
+
+pub fn select_first(document: &Document, root: NodeId, selectors: &SelectorList)
+                    -> Option<NodeId> {
+    document.descendants(root).find(|&node| selectors.matches(document, node))
+}
+
+fn namespace_for_prefix(prefix: &str) -> ParseResult<Ns> {
+    match prefix.to_ascii_lower().as_slice() {
+        "html" => Ok(HtmlNs),
+        "svg" => Ok(SvgNs),
+        "math" => Ok(MathMlNs),
+        "xlink" => Ok(XLinkNs),
+        "xml" => Ok(XmlNs),
+        "xmlns" => Ok(XmlNsNs),
+        _ => Err(())
+    }
+}
+
+struct SelectorParser {
+    input: Vec<char>,
+    position: uint,
+}
+
+impl SelectorParser {
+    fn peek_at(&self, offset: uint) -> Option<char> {
+        if self.position + offset < self.input.len() {
+            Some(*self.input.get(self.position + offset))
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.position += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> ParseResult<()> {
+        if self.eat(expected) { Ok(()) } else { Err(()) }
+    }
+
+    // Returns true if there was any whitespace.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+        while self.peek().map_or(false, is_html_whitespace) {
+            self.position += 1;
+        }
+        self.position != start
+    }
+
+    fn starts_ident(&self) -> bool {
+        let is_start = |c: Option<char>| c.map_or(false, |c| {
+            c.is_alphabetic() || c == '_' || !c.is_ascii()
+        });
+        match self.peek() {
+            Some('-') => is_start(self.peek_at(1)) || self.peek_at(1) == Some('-') ||
+                         self.peek_at(1) == Some('\\'),
+            Some('\\') => self.peek_at(1).map_or(false, |c| c != '\n'),
+            c => is_start(c)
+        }
+    }
+
+    fn ident(&mut self) -> ParseResult<String> {
+        if !self.starts_ident() {
+            return Err(())
+        }
+        let mut ident = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.next();
+                    ident.push_char(try!(self.escape()));
+                }
+                Some(c) if c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii() => {
+                    self.next();
+                    ident.push_char(c);
+                }
+                _ => return Ok(ident)
+            }
+        }
+    }
+
+    // The character escaped after a backslash.
+    fn escape(&mut self) -> ParseResult<char> {
+        let mut hex = String::new();
+        while hex.len() < 6 && self.peek().map_or(false, |c| c.is_digit_radix(16)) {
+            hex.push_char(self.next().unwrap());
+        }
+        if hex.is_empty() {
+            return match self.next() {
+                Some(c) => Ok(c),
+                None => Err(())
+            }
+        }
+        if self.peek().map_or(false, is_html_whitespace) {
+            self.next();
+        }
+        let code = num::from_str_radix::<u32>(hex.as_slice(), 16).unwrap();
+        Ok(match char::from_u32(code) {
+            Some(c) if c != '\0' => c,
+            _ => '\ufffd'
+        })
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        let quote = self.next().unwrap();
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                None => return Ok(string),
+                Some(c) if c == quote => return Ok(string),
+                Some('\n') => return Err(()),
+                Some('\\') => {
+                    match self.peek() {
+                        Some('\n') => { self.next(); }
+                        None => {}
+                        _ => string.push_char(try!(self.escape())),
+                    }
+                }
+                Some(c) => string.push_char(c),
+            }
+        }
+    }
+
+    // Parses selectors up to the end of the input or a closing parenthesis. Relative selectors,
+    // as in `:has()`, may start with a combinator.
+    fn selector_list(&mut self, relative: bool) -> ParseResult<SelectorList> {
+        let mut selectors = Vec::new();
+        loop {
+            selectors.push(try!(self.complex_selector(relative)));
+            self.skip_whitespace();
+            if !self.eat(',') {
+                return Ok(SelectorList {
+                    selectors: selectors,
+                })
+            }
+            self.skip_whitespace();
+        }
+    }
+
+    fn combinator(&mut self) -> Option<Combinator> {
+        let combinator = match self.peek() {
+            Some('>') => Child,
+            Some('+') => NextSibling,
+            Some('~') => SubsequentSibling,
+            _ => return None
+        };
+        self.next();
+        Some(combinator)
+    }
+
+    fn complex_selector(&mut self, relative: bool) -> ParseResult<ComplexSelector> {
+        let mut compounds = Vec::new();
+        if relative {
+            let combinator = self.combinator().unwrap_or(Descendant);
+            self.skip_whitespace();
+            compounds.push((Descendant, vec!(ScopeSelector)));
+            compounds.push((combinator, try!(self.compound_selector())));
+        } else {
+            compounds.push((Descendant, try!(self.compound_selector())));
+        }
+        loop {
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.combinator() {
+                Some(combinator) => {
+                    self.skip_whitespace();
+                    combinator
+                }
+                None => match self.peek() {
+                    Some(',') | Some(')') | None => break,
+                    _ if whitespace => Descendant,
+                    _ => return Err(())
+                }
+            };
+            compounds.push((combinator, try!(self.compound_selector())));
+        }
+        Ok(ComplexSelector {
+            compounds: compounds,
+        })
+    }
+
+    fn compound_selector(&mut self) -> ParseResult<Vec<SimpleSelector>> {
+        let mut selectors = Vec::new();
+        match try!(self.type_selector()) {
+            Some(selector) => selectors.push(selector),
+            None => {}
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.next();
+                    selectors.push(IdSelector(try!(self.ident())));
+                }
+                Some('.') => {
+                    self.next();
+                    selectors.push(ClassSelector(try!(self.ident())));
+                }
+                Some('[') => {
+                    self.next();
+                    selectors.push(AttributeSelectorOf(try!(self.attribute_selector())));
+                }
+                Some(':') => {
+                    self.next();
+                    selectors.push(try!(self.pseudo_class()));
+                }
+                _ => break
+            }
+        }
+        if selectors.is_empty() { Err(()) } else { Ok(selectors) }
+    }
+
+    // Parses `prefix|name`, `*|name`, `|name` or `name`, where the name may be `*`. Returns the
+    // namespace, if there was a prefix, and the name, or None for `*`.
+    fn qualified_name(&mut self) -> ParseResult<Option<(Option<NamespaceConstraint>,
+                                                         Option<String>)>> {
+        let first = if self.eat('*') {
+            Some(None)
+        } else if self.starts_ident() {
+            Some(Some(try!(self.ident())))
+        } else if self.peek() == Some('|') {
+            None
+        } else {
+            return Ok(None)
+        };
+        if self.peek() != Some('|') || self.peek_at(1) == Some('=') {
+            return match first {
+                Some(name) => Ok(Some((None, name))),
+                None => Err(())
+            }
+        }
+        self.next();
+        let ns = match first {
+            None => SomeNs(NullNs),
+            Some(None) => AnyNs,
+            Some(Some(prefix)) => SomeNs(try!(namespace_for_prefix(prefix.as_slice()))),
+        };
+        let name = if self.eat('*') { None } else { Some(try!(self.ident())) };
+        Ok(Some((Some(ns), name)))
+    }
+
+    fn type_selector(&mut self) -> ParseResult<Option<SimpleSelector>> {
+        Ok(match try!(self.qualified_name()) {
+            Some((ns, Some(name))) => {
+                let lower = name.as_slice().to_ascii_lower();
+                Some(TypeSelector(ns.unwrap_or(AnyNs), name, lower))
+            }
+            Some((ns, None)) => Some(UniversalSelector(ns.unwrap_or(AnyNs))),
+            None => None
+        })
+    }
+
+    fn attribute_selector(&mut self) -> ParseResult<AttributeSelector> {
+        self.skip_whitespace();
+        let (ns, name) = match try!(self.qualified_name()) {
+            // Attributes without a prefix are in no namespace.
+            Some((ns, Some(name))) => (ns.unwrap_or(SomeNs(NullNs)), name),
+            _ => return Err(())
+        };
+        self.skip_whitespace();
+        let operator = match self.next() {
+            Some(']') => {
+                return Ok(AttributeSelector {
+                    ns: ns,
+                    name: name,
+                    operator: Exists,
+                    value: String::new(),
+                    case_insensitive: false,
+                })
+            }
+            Some('=') => Equals,
+            Some(c) => {
+                let operator = match c {
+                    '~' => Includes,
+                    '|' => DashMatch,
+                    '^' => Prefix,
+                    '$' => Suffix,
+                    '*' => Substring,
+                    _ => return Err(())
+                };
+                try!(self.expect('='));
+                operator
+            }
+            None => return Err(())
+        };
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some('"') | Some('\'') => try!(self.string()),
+            _ => try!(self.ident()),
+        };
+        self.skip_whitespace();
+        let case_insensitive = match self.peek() {
+            Some('i') | Some('I') => {
+                self.next();
+                true
+            }
+            Some('s') | Some('S') => {
+                self.next();
+                false
+            }
+            _ => false
+        };
+        self.skip_whitespace();
+        try!(self.expect(']'));
+        Ok(AttributeSelector {
+            ns: ns,
+            name: name,
+            operator: operator,
+            value: value,
+            case_insensitive: case_insensitive,
+        })
+    }
+
+    fn pseudo_class(&mut self) -> ParseResult<SimpleSelector> {
+        if self.eat(':') {
+            try!(self.ident());
+            return Ok(NeverSelector)
+        }
+        let name = try!(self.ident()).as_slice().to_ascii_lower();
+        if self.eat('(') {
+            self.skip_whitespace();
+            let selector = match name.as_slice() {
+                "not" => NotSelector(try!(self.selector_list(false))),
+                "has" => HasSelector(try!(self.selector_list(true))),
+                "nth-child" => NthSelector(try!(self.nth(false, true))),
+                "nth-last-child" => NthSelector(try!(self.nth(true, true))),
+                "nth-of-type" => NthSelector(try!(self.nth(false, false))),
+                "nth-last-of-type" => NthSelector(try!(self.nth(true, false))),
+                "lang" => {
+                    LangSelector(match self.peek() {
+                        Some('"') | Some('\'') => try!(self.string()),
+                        _ => try!(self.ident()),
+                    })
+                }
+                _ => return Err(())
+            };
+            self.skip_whitespace();
+            try!(self.expect(')'));
+            return Ok(selector)
+        }
+        let nth = |a: int, b: int, from_end: bool, of_type: bool| NthSelector(Nth {
+            a: a,
+            b: b,
+            of: None,
+            from_end: from_end,
+            of_type: of_type,
+        });
+        Ok(match name.as_slice() {
+            "root" => RootSelector,
+            "empty" => EmptySelector,
+            "first-child" => nth(0, 1, false, false),
+            "last-child" => nth(0, 1, true, false),
+            "first-of-type" => nth(0, 1, false, true),
+            "last-of-type" => nth(0, 1, true, true),
+            "only-child" => {
+                NotSelector(SelectorList {
+                    selectors: vec!(ComplexSelector {
+                        compounds: vec!((Descendant, vec!(nth(1, 2, false, false)))),
+                    }, ComplexSelector {
+                        compounds: vec!((Descendant, vec!(nth(1, 2, true, false)))),
+                    }),
+                })
+            }
+            "only-of-type" => {
+                NotSelector(SelectorList {
+                    selectors: vec!(ComplexSelector {
+                        compounds: vec!((Descendant, vec!(nth(1, 2, false, true)))),
+                    }, ComplexSelector {
+                        compounds: vec!((Descendant, vec!(nth(1, 2, true, true)))),
+                    }),
+                })
+            }
+            "link" | "any-link" => LinkSelector,
+            "checked" => CheckedSelector,
+            "disabled" => DisabledSelector,
+            "enabled" => EnabledSelector,
+            "visited" | "hover" | "active" | "focus" | "focus-within" | "focus-visible" |
+            "target" | "indeterminate" => NeverSelector,
+            // The pseudo-elements that can be written with a single colon.
+            "before" | "after" | "first-line" | "first-letter" => NeverSelector,
+            _ => return Err(())
+        })
+    }
+
+    // Parses the argument of an `:nth-*()` pseudo-class, up to the closing parenthesis.
+    fn nth(&mut self, from_end: bool, allow_of: bool) -> ParseResult<Nth> {
+        let mut text = String::new();
+        let mut of = false;
+        loop {
+            match self.peek() {
+                None => return Err(()),
+                Some(')') => break,
+                Some(c) if is_html_whitespace(c) => {
+                    self.skip_whitespace();
+                    let keyword = self.peek().map_or(false, |c| c == 'o' || c == 'O') &&
+                        self.peek_at(1).map_or(false, |c| c == 'f' || c == 'F') &&
+                        self.peek_at(2).map_or(false, is_html_whitespace);
+                    if allow_of && keyword {
+                        self.position += 2;
+                        self.skip_whitespace();
+                        of = true;
+                        break
+                    }
+                }
+                Some(c) => {
+                    self.next();
+                    text.push_char(c);
+                }
+            }
+        }
+        let (a, b) = try!(parse_an_b(text.as_slice()));
+        let of = if of { Some(try!(self.selector_list(false))) } else { None };
+        Ok(Nth {
+            a: a,
+            b: b,
+            of: of,
+            from_end: from_end,
+            of_type: !allow_of,
+        })
+    }
+}
+
+// Parses An+B with the whitespace already removed.
+fn parse_an_b(text: &str) -> ParseResult<(int, int)> {
+    let text = text.to_ascii_lower();
+    let integer = |text: &str| -> ParseResult<int> {
+        let text = if text.starts_with("+") { text.slice_from(1) } else { text };
+        if text.is_empty() || text.starts_with("+") || text.starts_with("-") && text.len() == 1 {
+            return Err(())
+        }
+        match from_str::<int>(text) {
+            Some(integer) => Ok(integer),
+            None => Err(())
+        }
+    };
+    match text.as_slice() {
+        "odd" => return Ok((2, 1)),
+        "even" => return Ok((2, 0)),
+        _ => {}
+    }
+    match text.as_slice().find('n') {
+        Some(index) => {
+            let a = match text.as_slice().slice_to(index) {
+                "" | "+" => 1,
+                "-" => -1,
+                a => try!(integer(a)),
+            };
+            let b = text.as_slice().slice_from(index + 1);
+            let b = if b.is_empty() {
+                0
+            } else if b.starts_with("+") || b.starts_with("-") {
+                try!(integer(b))
+            } else {
+                return Err(())
+            };
+            Ok((a, b))
+        }
+        None => Ok((0, try!(integer(text.as_slice())))),
+    }
+}
+
+fn parent_element(document: &Document, node: NodeId) -> Option<NodeId> {
+    match document.parent(node) {
+        Some(parent) if document.tag(parent).is_some() => Some(parent),
+        _ => None
+    }
+}
+
+fn previous_element(document: &Document, node: NodeId) -> Option<NodeId> {
+    let mut sibling = document.prev_sibling(node);
+    loop {
+        match sibling {
+            Some(id) if document.tag(id).is_none() => sibling = document.prev_sibling(id),
+            _ => return sibling
+        }
+    }
+}
+
+fn next_element(document: &Document, node: NodeId) -> Option<NodeId> {
+    let mut sibling = document.next_sibling(node);
+    loop {
+        match sibling {
+            Some(id) if document.tag(id).is_none() => sibling = document.next_sibling(id),
+            _ => return sibling
+        }
+    }
+}
+
+fn matches_ns(constraint: &NamespaceConstraint, ns: Ns) -> bool {
+    match *constraint {
+        AnyNs => true,
+        SomeNs(expected) => expected == ns,
+    }
+}
+
+impl ComplexSelector {
+    fn matches(&self, document: &Document, node: NodeId, scope: Option<NodeId>) -> bool {
+        self.matches_from(self.compounds.len() - 1, document, node, scope)
+    }
+
+    fn matches_from(&self, index: uint, document: &Document, node: NodeId,
+                    scope: Option<NodeId>) -> bool {
+        let &(ref combinator, ref compound) = self.compounds.get(index);
+        if !compound.iter().all(|selector| selector.matches(document, node, scope)) {
+            return false
+        }
+        if index == 0 {
+            return true
+        }
+        match *combinator {
+            Child => parent_element(document, node).map_or(false, |parent| {
+                self.matches_from(index - 1, document, parent, scope)
+            }),
+            Descendant => {
+                let mut ancestor = parent_element(document, node);
+                loop {
+                    match ancestor {
+                        Some(id) => {
+                            if self.matches_from(index - 1, document, id, scope) {
+                                return true
+                            }
+                            ancestor = parent_element(document, id);
+                        }
+                        None => return false
+                    }
+                }
+            }
+            NextSibling => previous_element(document, node).map_or(false, |sibling| {
+                self.matches_from(index - 1, document, sibling, scope)
+            }),
+            SubsequentSibling => {
+                let mut sibling = previous_element(document, node);
+                loop {
+                    match sibling {
+                        Some(id) => {
+                            if self.matches_from(index - 1, document, id, scope) {
+                                return true
+                            }
+                            sibling = previous_element(document, id);
+                        }
+                        None => return false
+                    }
+                }
+            }
+        }
+    }
+
+    // The combinator a relative selector starts with.
+    fn relative_combinator(&self) -> Combinator {
+        let &(ref combinator, _) = self.compounds.get(1);
+        combinator.clone()
+    }
+}
+
+impl SimpleSelector {
+    fn depends_on_descendants(&self) -> bool {
+        match *self {
+            HasSelector(_) | EmptySelector => true,
+            NotSelector(ref list) => list.depends_on_descendants(),
+            NthSelector(Nth { of: Some(ref list), .. }) => list.depends_on_descendants(),
+            _ => false
+        }
+    }
+
+    // Relative selectors in `:has()` only look at later siblings.
+    fn depends_on_siblings(&self) -> bool {
+        match *self {
+            NthSelector(_) => true,
+            NotSelector(ref list) => list.depends_on_siblings(),
+            _ => false
+        }
+    }
+
+    fn matches(&self, document: &Document, node: NodeId, scope: Option<NodeId>) -> bool {
+        let tag = match document.tag(node) {
+            Some(tag) => tag,
+            None => return false
+        };
+        match *self {
+            TypeSelector(ref ns, ref name, ref lower) => {
+                matches_ns(ns, tag.ns) && if tag.ns == HtmlNs {
+                    tag.name == *lower
+                } else {
+                    tag.name == *name
+                }
+            }
+            UniversalSelector(ref ns) => matches_ns(ns, tag.ns),
+            IdSelector(ref id) => tag.get_attribute("id") == Some(id.as_slice()),
+            ClassSelector(ref class) => {
+                tag.get_attribute("class").map_or(false, |classes| {
+                    classes.split(is_html_whitespace).any(|c| c == class.as_slice())
+                })
+            }
+            AttributeSelectorOf(ref selector) => {
+                tag.attributes.iter().any(|attribute| selector.matches(tag, attribute))
+            }
+            NthSelector(ref nth) => nth.matches(document, node, tag),
+            NotSelector(ref list) => !list.matches_scoped(document, node, scope),
+            HasSelector(ref list) => has(list, document, node),
+            LangSelector(ref lang) => {
+                let mut element = Some(node);
+                loop {
+                    let id = match element {
+                        Some(id) => id,
+                        None => return false
+                    };
+                    let tag = document.tag(id).unwrap();
+                    let value = tag.attributes.iter().find(|attribute| {
+                        attribute.name.as_slice() == "lang" &&
+                            (attribute.ns == NullNs || attribute.ns == XmlNs)
+                    });
+                    match value {
+                        Some(attribute) => {
+                            let value = attribute.value.as_slice();
+                            return value.eq_ignore_ascii_case(lang.as_slice()) ||
+                                value.len() > lang.len() &&
+                                value.slice_to(lang.len()).eq_ignore_ascii_case(lang.as_slice()) &&
+                                value.char_at(lang.len()) == '-'
+                        }
+                        None => element = parent_element(document, id),
+                    }
+                }
+            }
+            RootSelector => {
+                document.parent(node).map_or(false, |parent| {
+                    match document.node(parent).data {
+                        DocumentNode => true,
+                        _ => false
+                    }
+                })
+            }
+            EmptySelector => {
+                document.children(node).all(|child| {
+                    match document.node(child).data {
+                        CommentNode(_) | DoctypeNode(_) => true,
+                        _ => false
+                    }
+                })
+            }
+            LinkSelector => {
+                tag.is_html_element_in(&["a", "area", "link"]) && tag.has_attribute("href")
+            }
+            CheckedSelector => {
+                let input_type = tag.get_attribute("type").unwrap_or("");
+                tag.is_html_element_in(&["input"]) && tag.has_attribute("checked") &&
+                    (input_type.eq_ignore_ascii_case("checkbox") ||
+                     input_type.eq_ignore_ascii_case("radio")) ||
+                    tag.is_html_element_in(&["option"]) && tag.has_attribute("selected")
+            }
+            DisabledSelector => is_form_control(tag) && tag.has_attribute("disabled"),
+            EnabledSelector => is_form_control(tag) && !tag.has_attribute("disabled"),
+            ScopeSelector => scope == Some(node),
+            NeverSelector => false,
+        }
+    }
+}
+
+fn is_form_control(tag: &Tag) -> bool {
+    tag.is_html_element_in(&["button", "input", "select", "textarea", "optgroup", "option",
+                             "fieldset"])
+}
+
+// Whether `node` matches `compound`, taking the pseudo-classes that depend on its content or on
+// later siblings to match.
+fn could_match_compound(compound: &[SimpleSelector], document: &Document, node: NodeId) -> bool {
+    compound.iter().all(|simple| {
+        match *simple {
+            NthSelector(_) | NotSelector(_) | HasSelector(_) | EmptySelector => true,
+            ref simple => simple.matches(document, node, None),
+        }
+    })
+}
+
+// Whether any element relative to `node` matches one of the relative selectors in `list`.
+fn has(list: &SelectorList, document: &Document, node: NodeId) -> bool {
+    list.selectors.iter().any(|selector| {
+        match selector.relative_combinator() {
+            Descendant | Child => document.descendants(node).any(|candidate| {
+                has_match(selector, document, candidate, node)
+            }),
+            NextSibling | SubsequentSibling => {
+                // Later siblings, and for compound selectors further on, their descendants.
+                let mut sibling = next_element(document, node);
+                loop {
+                    match sibling {
+                        Some(id) => {
+                            if has_match(selector, document, id, node) ||
+                                    document.descendants(id).any(|candidate| {
+                                        has_match(selector, document, candidate, node)
+                                    }) {
+                                return true
+                            }
+                            sibling = next_element(document, id);
+                        }
+                        None => return false
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn has_match(selector: &ComplexSelector, document: &Document, candidate: NodeId, anchor: NodeId)
+             -> bool {
+    document.tag(candidate).is_some() && selector.matches(document, candidate, Some(anchor))
+}
+
+impl AttributeSelector {
+    fn matches(&self, tag: &Tag, attribute: &Attribute) -> bool {
+        if !matches_ns(&self.ns, attribute.ns) {
+            return false
+        }
+        // Attribute names of HTML elements are matched case-insensitively.
+        let name_matches = if tag.ns == HtmlNs {
+            attribute.name.as_slice().eq_ignore_ascii_case(self.name.as_slice())
+        } else {
+            attribute.name == self.name
+        };
+        if !name_matches {
+            return false
+        }
+        let (value, expected) = if self.case_insensitive {
+            (attribute.value.as_slice().to_ascii_lower(), self.value.as_slice().to_ascii_lower())
+        } else {
+            (attribute.value.clone(), self.value.clone())
+        };
+        let (value, expected) = (value.as_slice(), expected.as_slice());
+        match self.operator {
+            Exists => true,
+            Equals => value == expected,
+            Includes => {
+                !expected.is_empty() && value.split(is_html_whitespace).any(|v| v == expected)
+            }
+            DashMatch => {
+                value == expected || value.starts_with(expected) &&
+                    value.slice_from(expected.len()).starts_with("-")
+            }
+            Prefix => !expected.is_empty() && value.starts_with(expected),
+            Suffix => !expected.is_empty() && value.ends_with(expected),
+            Substring => !expected.is_empty() && value.contains(expected),
+        }
+    }
+}
+
+impl Nth {
+    fn matches(&self, document: &Document, node: NodeId, tag: &Tag) -> bool {
+        match self.of {
+            Some(ref list) if !list.matches(document, node) => return false,
+            _ => {}
+        }
+        let counts = |sibling: NodeId| {
+            match document.tag(sibling) {
+                Some(other) if self.of_type => other.ns == tag.ns && other.name == tag.name,
+                Some(_) => self.of.as_ref().map_or(true, |list| list.matches(document, sibling)),
+                None => false
+            }
+        };
+        // The 1-based position of the element among the siblings that count.
+        let mut index = 1i;
+        let mut sibling = if self.from_end {
+            next_element(document, node)
+        } else {
+            previous_element(document, node)
+        };
+        loop {
+            match sibling {
+                Some(id) => {
+                    if counts(id) {
+                        index += 1;
+                    }
+                    sibling = if self.from_end {
+                        next_element(document, id)
+                    } else {
+                        previous_element(document, id)
+                    };
+                }
+                None => break
+            }
+        }
+        if self.a == 0 {
+            index == self.b
+        } else {
+            (index - self.b) % self.a == 0 && (index - self.b) / self.a >= 0
+        }
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Serialization of arena documents, either as HTML following the HTML fragment serialization
+// algorithm, or as well-formed XHTML.
+
+use std::io::{IoResult, MemWriter, Writer};
+use dom::{Document, NodeId, DocumentNode, DocumentFragmentNode, DoctypeNode, CommentNode, TextNode};
+use dom::{ElementNode, ShadowRootNode};
+use hubbub::{Attribute, Tag, Ns, NullNs, HtmlNs, MathMlNs, SvgNs, XLinkNs, XmlNs, XmlNsNs};
+use hubbub::{ShadowRootInit, OpenShadowRoot, ClosedShadowRoot};
+
+pub enum TraversalScope {
+    IncludeNode,
+    ChildrenOnly,
+}
+
+static VOID_ELEMENTS: &'static [&'static str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+static RAW_TEXT_ELEMENTS: &'static [&'static str] = &[
+    "style", "script", "xmp", "iframe", "noembed", "noframes", "plaintext",
+];
+
+pub fn is_void_element(tag: &Tag) -> bool {
+    tag.is_html_element_in(VOID_ELEMENTS)
+}
+
+// For tags straight from the tokenizer, which have no namespace yet.
+pub fn is_void_element_name(name: &str) -> bool {
+    VOID_ELEMENTS.iter().any(|void| name == *void)
+}
+
+pub fn serialize(writer: &mut Writer, document: &Document, node: NodeId, scope: TraversalScope)
+                 -> IoResult<()> {
+    match scope {
+        IncludeNode => serialize_node(writer, document, node),
+        ChildrenOnly => serialize_children(writer, document, node),
+    }
+}
+
+pub fn to_string(document: &Document, node: NodeId, scope: TraversalScope) -> String {
+    let mut writer = MemWriter::new();
+    serialize(&mut writer, document, node, scope).unwrap();
+    String::from_utf8(writer.unwrap()).unwrap()
+}
+
+fn serialize_children(writer: &mut Writer, document: &Document, node: NodeId) -> IoResult<()> {
+    for child in document.children(node) {
+        try!(serialize_node(writer, document, child));
+    }
+    Ok(())
+}
+
+fn serialize_node(writer: &mut Writer, document: &Document, node: NodeId) -> IoResult<()> {
+    match document.node(node).data {
+        DocumentNode | DocumentFragmentNode | ShadowRootNode(_) => {
+            serialize_children(writer, document, node)
+        }
+        DoctypeNode(ref doctype) => {
+            try!(writer.write_str("<!DOCTYPE "));
+            try!(writer.write_str(doctype.name.as_slice()));
+            writer.write_str(">")
+        }
+        CommentNode(ref data) => {
+            try!(writer.write_str("<!--"));
+            try!(writer.write_str(data.as_slice()));
+            writer.write_str("-->")
+        }
+        TextNode(ref data) => {
+            let raw = match document.parent(node).and_then(|parent| document.tag(parent)) {
+                Some(parent) => parent.is_html_element_in(RAW_TEXT_ELEMENTS),
+                None => false
+            };
+            if raw {
+                writer.write_str(data.as_slice())
+            } else {
+                write_escaped(writer, data.as_slice(), false)
+            }
+        }
+        ElementNode(ref tag) => {
+            try!(writer.write_str("<"));
+            try!(writer.write_str(tag.name.as_slice()));
+            for attribute in tag.attributes.iter() {
+                try!(writer.write_str(" "));
+                try!(writer.write_str(attribute_name(attribute).as_slice()));
+                try!(writer.write_str("=\""));
+                try!(write_escaped(writer, attribute.value.as_slice(), true));
+                try!(writer.write_str("\""));
+            }
+            try!(writer.write_str(">"));
+
+            if is_void_element(tag) {
+                return Ok(())
+            }
+
+            match document.shadow_root_init(node) {
+                Some(init) => {
+                    try!(write_shadow_root_start_tag(writer, &init));
+                    try!(serialize_children(writer, document, document.shadow_root(node).unwrap()));
+                    try!(writer.write_str("</template>"));
+                }
+                None => {}
+            }
+            try!(serialize_children(writer, document, children_parent(document, node)));
+            try!(writer.write_str("</"));
+            try!(writer.write_str(tag.name.as_slice()));
+            writer.write_str(">")
+        }
+    }
+}
+
+// The node whose children are serialized as the contents of `node`: the template contents for
+// template elements, and the node itself otherwise.
+fn children_parent(document: &Document, node: NodeId) -> NodeId {
+    document.template_contents(node).unwrap_or(node)
+}
+
+// Shadow roots are serialized as the declarative shadow root templates that create them, before
+// the children of their host.
+fn write_shadow_root_start_tag(writer: &mut Writer, init: &ShadowRootInit) -> IoResult<()> {
+    try!(writer.write_str(match init.mode {
+        OpenShadowRoot => "<template shadowrootmode=\"open\"",
+        ClosedShadowRoot => "<template shadowrootmode=\"closed\"",
+    }));
+    if init.delegates_focus {
+        try!(writer.write_str(" shadowrootdelegatesfocus=\"\""));
+    }
+    if init.clonable {
+        try!(writer.write_str(" shadowrootclonable=\"\""));
+    }
+    writer.write_str(">")
+}
+
+// The serialized name of an attribute, with the prefix implied by its namespace.
+pub fn attribute_name(attribute: &Attribute) -> String {
+    match attribute.ns {
+        XmlNsNs if attribute.name.as_slice() == "xmlns" => attribute.name.clone(),
+        ns => match namespace_prefix(ns) {
+            Some(prefix) => format!("{}:{}", prefix, attribute.name),
+            None => attribute.name.clone(),
+        }
+    }
+}
+
+pub fn namespace_prefix(ns: Ns) -> Option<&'static str> {
+    match ns {
+        XmlNs => Some("xml"),
+        XmlNsNs => Some("xmlns"),
+        XLinkNs => Some("xlink"),
+        NullNs | HtmlNs | MathMlNs | SvgNs => None,
+    }
+}
+
+// Escapes text as the serialization algorithm requires: `&`, no-break spaces, and either `"` in
+// attribute mode or `<` and `>` otherwise.
+pub fn write_escaped(writer: &mut Writer, text: &str, attribute_mode: bool) -> IoResult<()> {
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        let replacement = match c {
+            '&' => "&amp;",
+            '\u00a0' => "&nbsp;",
+            '"' if attribute_mode => "&quot;",
+            // Also escaped in attribute values, which can end up parsed as markup when an
+            // element is parsed differently than it was serialized, as `noscript` can be.
+            '<' => "&lt;",
+            '>' => "&gt;",
+            _ => continue
+        };
+        try!(writer.write_str(text.slice(start, index)));
+        try!(writer.write_str(replacement));
+        start = index + c.len_utf8_bytes();
+    }
+    writer.write_str(text.slice_from(start))
+}
+
+pub fn namespace_url(ns: Ns) -> &'static str {
+    match ns {
+        NullNs => "",
+        HtmlNs => "http://www.w3.org/1999/xhtml",
+        MathMlNs => "http://www.w3.org/1998/Math/MathML",
+        SvgNs => "http://www.w3.org/2000/svg",
+        XLinkNs => "http://www.w3.org/1999/xlink",
+        XmlNs => "http://www.w3.org/XML/1998/namespace",
+        XmlNsNs => "http://www.w3.org/2000/xmlns/",
+    }
+}
+
+// XHTML serialization. Namespace declarations are generated from the `Ns` of each element and
+// attribute, so any `xmlns` attributes that the HTML parser left in the tree are dropped.
+pub fn serialize_xhtml(writer: &mut Writer, document: &Document, node: NodeId,
+                       scope: TraversalScope) -> IoResult<()> {
+    let context = XmlContext {
+        default_ns: None,
+        xlink_declared: false,
+    };
+    match scope {
+        IncludeNode => serialize_xhtml_node(writer, document, node, context),
+        ChildrenOnly => serialize_xhtml_children(writer, document, node, context),
+    }
+}
+
+pub fn to_xhtml_string(document: &Document, node: NodeId, scope: TraversalScope) -> String {
+    let mut writer = MemWriter::new();
+    serialize_xhtml(&mut writer, document, node, scope).unwrap();
+    String::from_utf8(writer.unwrap()).unwrap()
+}
+
+// The namespace declarations in scope at a node.
+struct XmlContext {
+    default_ns: Option<Ns>,
+    xlink_declared: bool,
+}
+
+fn serialize_xhtml_children(writer: &mut Writer, document: &Document, node: NodeId,
+                            context: XmlContext) -> IoResult<()> {
+    for child in document.children(node) {
+        try!(serialize_xhtml_node(writer, document, child, context));
+    }
+    Ok(())
+}
+
+fn serialize_xhtml_node(writer: &mut Writer, document: &Document, node: NodeId,
+                        context: XmlContext) -> IoResult<()> {
+    match document.node(node).data {
+        DocumentNode | DocumentFragmentNode | ShadowRootNode(_) => {
+            serialize_xhtml_children(writer, document, node, context)
+        }
+        DoctypeNode(ref doctype) => {
+            try!(writer.write_str("<!DOCTYPE "));
+            try!(writer.write_str(coerce_xml_name(doctype.name.as_slice()).as_slice()));
+            writer.write_str(">")
+        }
+        CommentNode(ref data) => {
+            // XML comments may not contain "--" or end with "-", so a space goes between any two
+            // hyphens and after a final one.
+            let mut comment = String::new();
+            for c in data.as_slice().chars() {
+                if c == '-' && comment.as_slice().ends_with("-") {
+                    comment.push_char(' ');
+                }
+                comment.push_char(if is_xml_char(c) { c } else { '\ufffd' });
+            }
+            if comment.as_slice().ends_with("-") {
+                comment.push_char(' ');
+            }
+            try!(writer.write_str("<!--"));
+            try!(writer.write_str(comment.as_slice()));
+            writer.write_str("-->")
+        }
+        TextNode(ref data) => write_xml_escaped(writer, data.as_slice(), false),
+        ElementNode(ref tag) => {
+            let name = coerce_xml_name(tag.name.as_slice());
+            try!(writer.write_str("<"));
+            try!(writer.write_str(name.as_slice()));
+
+            let mut inner = XmlContext {
+                default_ns: Some(tag.ns),
+                xlink_declared: context.xlink_declared,
+            };
+            if context.default_ns != Some(tag.ns) {
+                try!(write!(writer, " xmlns=\"{}\"", namespace_url(tag.ns)));
+            }
+            let uses_xlink = tag.attributes.iter().any(|attribute| attribute.ns == XLinkNs);
+            if uses_xlink && !context.xlink_declared {
+                try!(write!(writer, " xmlns:xlink=\"{}\"", namespace_url(XLinkNs)));
+                inner.xlink_declared = true;
+            }
+
+            for attribute in tag.attributes.iter() {
+                let name = match attribute.ns {
+                    XmlNsNs => continue,
+                    NullNs if attribute.name.as_slice() == "xmlns" ||
+                              attribute.name.as_slice().starts_with("xmlns:") => continue,
+                    XLinkNs | XmlNs => {
+                        format!("{}:{}", namespace_prefix(attribute.ns).unwrap(),
+                                coerce_xml_name(attribute.name.as_slice()))
+                    }
+                    _ => coerce_xml_name(attribute.name.as_slice()),
+                };
+                try!(writer.write_str(" "));
+                try!(writer.write_str(name.as_slice()));
+                try!(writer.write_str("=\""));
+                try!(write_xml_escaped(writer, attribute.value.as_slice(), true));
+                try!(writer.write_str("\""));
+            }
+
+            // Void elements are self-closed; other empty HTML elements keep an end tag so that
+            // the output still works when served as text/html.
+            let children_parent = children_parent(document, node);
+            let empty = document.first_child(children_parent).is_none() &&
+                document.shadow_root(node).is_none();
+            if is_void_element(tag) || (empty && tag.ns != HtmlNs) {
+                return writer.write_str(" />")
+            }
+
+            try!(writer.write_str(">"));
+            match document.shadow_root_init(node) {
+                Some(init) => {
+                    try!(write_shadow_root_start_tag(writer, &init));
+                    try!(serialize_xhtml_children(writer, document,
+                                                  document.shadow_root(node).unwrap(), inner));
+                    try!(writer.write_str("</template>"));
+                }
+                None => {}
+            }
+            try!(serialize_xhtml_children(writer, document, children_parent, inner));
+            try!(writer.write_str("</"));
+            try!(writer.write_str(name.as_slice()));
+            writer.write_str(">")
+        }
+    }
+}
+
+fn write_xml_escaped(writer: &mut Writer, text: &str, attribute_mode: bool) -> IoResult<()> {
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        let replacement = match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' if attribute_mode => "&quot;",
+            '\t' if attribute_mode => "&#9;",
+            '\n' if attribute_mode => "&#10;",
+            '\r' => "&#13;",
+            // Characters that can't appear in XML at all, even as references.
+            c if !is_xml_char(c) => "\ufffd",
+            _ => continue
+        };
+        try!(writer.write_str(text.slice(start, index)));
+        try!(writer.write_str(replacement));
+        start = index + c.len_utf8_bytes();
+    }
+    writer.write_str(text.slice_from(start))
+}
+
+// Makes a name that the HTML parser accepted into a valid XML name, replacing every character
+// that isn't allowed at its position by `U` followed by its code point in hex, as html5lib does.
+// Colons are replaced too, since prefixes come from the namespace instead.
+pub fn coerce_xml_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    for (index, c) in name.char_indices() {
+        let valid = if index == 0 {
+            is_xml_name_start_char(c)
+        } else {
+            is_xml_name_start_char(c) || is_xml_name_char(c)
+        };
+        if valid {
+            result.push_char(c);
+        } else {
+            result.push_str(format!("U{:05X}", c as u32).as_slice());
+        }
+    }
+    result
+}
+
+fn is_xml_name_start_char(c: char) -> bool {
+    match c {
+        'a'..'z' | 'A'..'Z' | '_' => true,
+        '\u00c0'..'\u00d6' | '\u00d8'..'\u00f6' | '\u00f8'..'\u02ff' | '\u0370'..'\u037d' |
+        '\u037f'..'\u1fff' | '\u200c'..'\u200d' | '\u2070'..'\u218f' | '\u2c00'..'\u2fef' |
+        '\u3001'..'\ud7ff' | '\uf900'..'\ufdcf' | '\ufdf0'..'\ufffd' => true,
+        _ => c as u32 >= 0x10000 && c as u32 <= 0xeffff
+    }
+}
+
+fn is_xml_name_char(c: char) -> bool {
+    match c {
+        '-' | '.' | '0'..'9' | '\u00b7' | '\u0300'..'\u036f' | '\u203f'..'\u2040' => true,
+        _ => false
+    }
+}
+
+// Whether XML allows `c` in documents: control characters other than tab, line feed and carriage
+// return aren't allowed, and neither are the noncharacters U+FFFE and U+FFFF.
+fn is_xml_char(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => true,
+        '\ufffe' | '\uffff' => false,
+        c => c >= ' '
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Matching CSS selectors while parsing, without keeping the whole tree.
+//
+// Elements are matched as they finish, against their ancestors, their content and their earlier
+// siblings. Selectors that look at later siblings, such as `:last-child`, see none. Once an
+// element has finished it is discarded, unless a selector may still look at it. It is kept as an
+// empty element if a selector looks at earlier siblings, and keeps its text if an ancestor might
+// match and need it. Content is kept whole under elements that might match a selector that looks
+// at descendants, such as `:has()` or `:empty`.
+//
+// Discarded nodes the parser still refers to, as it does with formatting elements, are only
+// freed once it releases them, and the slots of freed nodes are reused for new ones.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use dom;
+use dom::{Document, NodeId, TextNode};
+use hubbub::{Parser, TreeObserver, NodeDataPtr, Tag, observe_tree};
+use select;
+use select::SelectorList;
+
+pub trait SelectorHandler {
+    // Called when an element matching the selectors finishes, with its text content.
+    fn element_matched(&mut self, tag: &Tag, text: &str);
+}
+
+struct SelectorEntry<'a> {
+    selectors: SelectorList,
+    handler: &'a mut SelectorHandler+'a,
+}
+
+pub struct StreamingSelectors<'a> {
+    entries: Vec<SelectorEntry<'a>>,
+    // Whether any of the selectors looks at descendants, or at earlier siblings.
+    depends_on_descendants: bool,
+    depends_on_siblings: bool,
+    arena_size: uint,
+}
+
+struct FinishedElementMatcher<'a, 'b> {
+    document: &'a RefCell<Document>,
+    selectors: &'a mut StreamingSelectors<'b>,
+    // How many references the parser holds to each node it holds any to.
+    references: HashMap<NodeDataPtr, uint>,
+}
+
+impl<'a, 'b> TreeObserver for FinishedElementMatcher<'a, 'b> {
+    fn element_finished(&mut self, element: NodeDataPtr) {
+        let mut document = self.document.borrow_mut();
+        let children = self.selectors.element_finished(&mut *document, element);
+        for &child in children.iter() {
+            self.discard(&mut *document, child);
+        }
+    }
+
+    fn node_referenced(&mut self, node: NodeDataPtr) {
+        *self.references.find_or_insert(node, 0) += 1;
+    }
+
+    fn node_released(&mut self, node: NodeDataPtr) {
+        let released = match self.references.find_mut(&node) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false
+        };
+        if !released {
+            return
+        }
+        self.references.remove(&node);
+        // Nodes the parser lets go of outside the tree, such as discarded formatting elements
+        // or text merged into other text, are of no more use.
+        let mut document = self.document.borrow_mut();
+        let node = NodeId::from_node_ptr(node);
+        if node != document.root() && document.parent(node).is_none() {
+            self.discard(&mut *document, node);
+        }
+    }
+}
+
+impl<'a, 'b> FinishedElementMatcher<'a, 'b> {
+    fn is_referenced(&self, node: NodeId) -> bool {
+        self.references.contains_key(&node.to_node_ptr())
+    }
+
+    // Frees `node` and its descendants, except for the ones the parser still refers to, which
+    // are detached and freed once it releases them.
+    fn discard(&self, document: &mut Document, node: NodeId) {
+        if self.is_referenced(node) {
+            document.detach(node);
+            return
+        }
+        let referenced: Vec<NodeId> = document.descendants(node).filter(|&descendant| {
+            self.is_referenced(descendant)
+        }).collect();
+        for &descendant in referenced.iter() {
+            document.detach(descendant);
+        }
+        document.remove(node);
+    }
+}
+
+impl<'a> StreamingSelectors<'a> {
+    pub fn new() -> StreamingSelectors<'a> {
+        StreamingSelectors {
+            entries: Vec::new(),
+            depends_on_descendants: false,
+            depends_on_siblings: false,
+            arena_size: 0,
+        }
+    }
+
+    // How many node slots the document built by the last parse needed, which depends on the
+    // content kept rather than on the length of the input.
+    pub fn arena_size(&self) -> uint {
+        self.arena_size
+    }
+
+    pub fn add(&mut self, selectors: &str, handler: &'a mut SelectorHandler+'a) {
+        let selectors = match select::parse(selectors) {
+            Some(selectors) => selectors,
+            None => fail!("invalid selector: {}", selectors)
+        };
+        self.depends_on_descendants |= selectors.depends_on_descendants();
+        self.depends_on_siblings |= selectors.depends_on_siblings();
+        self.entries.push(SelectorEntry {
+            selectors: selectors,
+            handler: handler,
+        });
+    }
+
+    // Calls `f` with a parser that reports matching elements to the handlers. `f` feeds it its
+    // input, and should call `completed` on it at the end.
+    pub fn parse<R>(&mut self, encoding: &str, f: |&mut Parser| -> R) -> R {
+        let document = RefCell::new(Document::new());
+        let root = document.borrow().root().to_node_ptr();
+        let matcher = RefCell::new(FinishedElementMatcher {
+            document: &document,
+            selectors: self,
+            references: HashMap::new(),
+        });
+
+        let result = dom::with_tree_handler(&document, |tree_handler| {
+            observe_tree(tree_handler, &matcher, |handler| {
+                let mut parser = Parser::new(encoding, false);
+                parser.set_tree_handler(handler);
+                parser.set_document_node(root);
+                f(&mut parser)
+            })
+        });
+        let arena_size = document.borrow().nodes.len();
+        matcher.unwrap().selectors.arena_size = arena_size;
+        result
+    }
+
+    // Reports `node` to the handlers of the selectors it matches, and returns the nodes that are
+    // no longer needed: its children, or itself and the text before it.
+    fn element_finished(&mut self, document: &mut Document, node: NodeDataPtr) -> Vec<NodeId> {
+        let node = NodeId::from_node_ptr(node);
+        let tag = match document.tag(node) {
+            Some(tag) => tag.clone(),
+            None => return Vec::new()
+        };
+        let mut text = None;
+        for entry in self.entries.mut_iter() {
+            if entry.selectors.matches(document, node) {
+                if text.is_none() {
+                    text = Some(document.text_content(node));
+                }
+                entry.handler.element_matched(&tag, text.as_ref().unwrap().as_slice());
+            }
+        }
+
+        if self.content_needed(document, node) {
+            return Vec::new()
+        }
+
+        let keep_text = document.ancestors(node).any(|ancestor| {
+            self.entries.iter().any(|entry| entry.selectors.could_match(document, ancestor))
+        });
+        let text = match text {
+            Some(text) if keep_text => Some(text),
+            None if keep_text => Some(document.text_content(node)),
+            _ => None
+        };
+        if !keep_text && !self.depends_on_siblings {
+            let mut discarded = vec!(node);
+            let mut sibling = document.prev_sibling(node);
+            loop {
+                match sibling {
+                    Some(id) if !document.node(id).is_element() => {
+                        discarded.push(id);
+                        sibling = document.prev_sibling(id);
+                    }
+                    _ => return discarded
+                }
+            }
+        }
+        let children: Vec<NodeId> = document.children(node).collect();
+        for &child in children.iter() {
+            document.detach(child);
+        }
+        match text {
+            Some(text) => {
+                if !text.is_empty() {
+                    let text = document.create_node(TextNode(text));
+                    document.append(node, text);
+                }
+            }
+            None => {}
+        }
+        children
+    }
+
+    // Whether a selector that looks at descendants may still see the content of `node`, from
+    // `node` itself, from one of its ancestors or from an earlier sibling of either.
+    fn content_needed(&self, document: &Document, node: NodeId) -> bool {
+        if !self.depends_on_descendants {
+            return false
+        }
+        for element in Some(node).move_iter().chain(document.ancestors(node)) {
+            let mut candidate = Some(element);
+            loop {
+                let id = match candidate {
+                    Some(id) => id,
+                    None => break
+                };
+                if self.entries.iter().any(|entry| {
+                    entry.selectors.could_match_by_descendants(document, id)
+                }) {
+                    return true
+                }
+                candidate = document.prev_sibling(id);
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,368 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Extraction of structured data, such as schema.org descriptions, from parsed documents:
+// JSON-LD blocks, which are returned unparsed, and items described with Microdata or RDFa Lite
+// attributes. RDFa types and properties written as prefixed names, such as "og:title", are
+// expanded with the prefixes the prefix attributes define; the prefixes of the RDFa initial
+// context aren't predefined.
+
+use std::ascii::StrAsciiExt;
+use std::collections::{HashMap, HashSet};
+use dom::{Document, NodeId};
+use hubbub::{Tag, HtmlNs, is_html_whitespace};
+use url;
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum PropertyValue {
+    TextValue(String),
+    // An absolute URL, from an attribute such as href or src.
+    UrlValue(String),
+    ItemValue(Item),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Item {
+    // The types from itemtype or typeof.
+    pub types: Vec<String>,
+    // The global identifier from itemid or resource.
+    pub id: Option<String>,
+    // The RDFa vocabulary in effect, which relative types and properties are in.
+    pub vocab: Option<String>,
+    // Properties in document order. Names can repeat.
+    pub properties: Vec<(String, PropertyValue)>,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct StructuredData {
+    pub json_ld: Vec<String>,
+    pub microdata: Vec<Item>,
+    pub rdfa: Vec<Item>,
+}
+
+struct Extractor<'a> {
+    document: &'a Document,
+    base_url: String,
+    // The position of each element in tree order.
+    positions: HashMap<NodeId, uint>,
+    // The first element with each ID.
+    ids: HashMap<String, NodeId>,
+}
+
+// Extracts the structured data in a document, resolving URLs against its base URL.
+pub fn extract(document: &Document, document_url: &str) -> StructuredData {
+    let mut extractor = Extractor {
+        document: document,
+        base_url: url::document_base_url(document, document_url),
+        positions: HashMap::new(),
+        ids: HashMap::new(),
+    };
+    let mut json_ld = Vec::new();
+    let mut top_level_items = Vec::new();
+    for (position, node) in document.descendants(document.root()).enumerate() {
+        extractor.positions.insert(node, position);
+        let tag = match document.tag(node) {
+            Some(tag) => tag,
+            None => continue
+        };
+        match tag.get_attribute("id") {
+            Some(id) if !id.is_empty() && !extractor.ids.contains_key(&id.to_string()) => {
+                extractor.ids.insert(id.to_string(), node);
+            }
+            _ => {}
+        }
+        if tag.has_attribute("itemscope") && !tag.has_attribute("itemprop") {
+            top_level_items.push(node);
+        }
+        let is_json_ld = tag.ns == HtmlNs && tag.name.as_slice() == "script" &&
+            tag.get_attribute("type").map_or(false, |script_type| {
+                script_type.trim_chars(is_html_whitespace)
+                           .eq_ignore_ascii_case("application/ld+json")
+            });
+        if is_json_ld {
+            json_ld.push(document.text_content(node));
+        }
+    }
+
+    let microdata = top_level_items.iter().map(|&node| {
+        extractor.microdata_item(node, &mut vec!(node))
+    }).collect();
+    let mut rdfa = RdfaState {
+        items: Vec::new(),
+        top_level_items: Vec::new(),
+        prefixes: Vec::new(),
+    };
+    extractor.rdfa(document.root(), None, &mut rdfa);
+
+    StructuredData {
+        json_ld: json_ld,
+        microdata: microdata,
+        rdfa: rdfa.top_level_items,
+    }
+}
+
+fn tokens(value: &str) -> Vec<String> {
+    value.split(is_html_whitespace).filter(|token| !token.is_empty()).map(|token| {
+        token.to_string()
+    }).collect()
+}
+
+// An RDFa item being built, with the properties of its parent that it is the value of.
+struct OpenItem {
+    properties: Vec<String>,
+    item: Item,
+}
+
+struct RdfaState {
+    items: Vec<OpenItem>,
+    top_level_items: Vec<Item>,
+    // The prefixes in scope and the IRIs they stand for, innermost last.
+    prefixes: Vec<(String, String)>,
+}
+
+impl RdfaState {
+    // Expands a prefixed name whose prefix is in scope. Other names, such as absolute IRIs and
+    // terms, are left as they are.
+    fn expand(&self, name: String) -> String {
+        let colon = match name.as_slice().find(':') {
+            Some(colon) => colon,
+            None => return name
+        };
+        let prefix = name.as_slice().slice_to(colon).to_ascii_lower();
+        match self.prefixes.iter().rev().find(|&&(ref other, _)| *other == prefix) {
+            Some(&(_, ref iri)) => format!("{}{}", iri, name.as_slice().slice_from(colon + 1)),
+            None => name
+        }
+    }
+}
+
+// The prefix mappings of a prefix attribute, such as "og: http://ogp.me/ns#".
+fn prefix_mappings(value: &str) -> Vec<(String, String)> {
+    let tokens = tokens(value);
+    let mut mappings = Vec::new();
+    for pair in tokens.as_slice().windows(2) {
+        let (prefix, iri) = (pair[0].as_slice(), pair[1].as_slice());
+        if prefix.len() > 1 && prefix.ends_with(":") && !iri.ends_with(":") {
+            mappings.push((prefix.slice_to(prefix.len() - 1).to_ascii_lower(), iri.to_string()));
+        }
+    }
+    mappings
+}
+
+impl<'a> Extractor<'a> {
+    fn resolve(&self, value: &str) -> String {
+        match url::resolve(self.base_url.as_slice(), value) {
+            Some(url) => url,
+            None => url::clean_url(value)
+        }
+    }
+
+    // Builds the Microdata item of an element with itemscope. `memory` holds the elements of
+    // the items being built, which properties referring back to are ignored.
+    fn microdata_item(&self, node: NodeId, memory: &mut Vec<NodeId>) -> Item {
+        let tag = self.document.tag(node).unwrap();
+        let mut item = Item {
+            types: tag.get_attribute("itemtype").map_or(Vec::new(), tokens),
+            // Only typed items have a global identifier.
+            id: if tag.has_attribute("itemtype") {
+                tag.get_attribute("itemid").and_then(|id| {
+                    url::resolve(self.base_url.as_slice(), id)
+                })
+            } else {
+                None
+            },
+            vocab: None,
+            properties: Vec::new(),
+        };
+        for property in self.microdata_properties(node).move_iter() {
+            if memory.contains(&property) {
+                continue
+            }
+            let property_tag = self.document.tag(property).unwrap();
+            let value = if property_tag.has_attribute("itemscope") {
+                memory.push(property);
+                let value = ItemValue(self.microdata_item(property, memory));
+                memory.pop();
+                value
+            } else {
+                self.microdata_value(property, property_tag)
+            };
+            for name in tokens(property_tag.get_attribute("itemprop").unwrap()).move_iter() {
+                item.properties.push((name, value.clone()));
+            }
+        }
+        item
+    }
+
+    // The elements holding the properties of an item, in tree order: its descendants and those
+    // of the elements it references with itemref, without going into nested items.
+    fn microdata_properties(&self, root: NodeId) -> Vec<NodeId> {
+        let mut pending: Vec<NodeId> = self.document.children(root).collect();
+        let tag = self.document.tag(root).unwrap();
+        for id in tag.get_attribute("itemref").map_or(Vec::new(), tokens).iter() {
+            match self.ids.find(id) {
+                Some(&node) => pending.push(node),
+                None => {}
+            }
+        }
+        let mut visited = HashSet::new();
+        visited.insert(root);
+        let mut properties = Vec::new();
+        loop {
+            let node = match pending.pop() {
+                Some(node) => node,
+                None => break
+            };
+            if !visited.insert(node) {
+                continue
+            }
+            let tag = match self.document.tag(node) {
+                Some(tag) => tag,
+                None => continue
+            };
+            if !tag.has_attribute("itemscope") {
+                pending.extend(self.document.children(node));
+            }
+            if tag.get_attribute("itemprop").map_or(false, |names| !tokens(names).is_empty()) {
+                properties.push(node);
+            }
+        }
+        properties.sort_by(|a, b| self.positions.find(a).cmp(&self.positions.find(b)));
+        properties
+    }
+
+    fn microdata_value(&self, node: NodeId, tag: &Tag) -> PropertyValue {
+        let attribute = if tag.ns != HtmlNs {
+            None
+        } else {
+            match tag.name.as_slice() {
+                "meta" => Some(("content", false)),
+                "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => {
+                    Some(("src", true))
+                }
+                "a" | "area" | "link" => Some(("href", true)),
+                "object" => Some(("data", true)),
+                "data" | "meter" => Some(("value", false)),
+                "time" if tag.has_attribute("datetime") => Some(("datetime", false)),
+                _ => None
+            }
+        };
+        match attribute {
+            // A URL that can't be resolved gives the empty string.
+            Some((name, true)) => {
+                let value = tag.get_attribute(name).and_then(|value| {
+                    url::resolve(self.base_url.as_slice(), value)
+                });
+                match value {
+                    Some(value) => UrlValue(value),
+                    None => TextValue(String::new())
+                }
+            }
+            Some((name, false)) => {
+                TextValue(tag.get_attribute(name).unwrap_or("").to_string())
+            }
+            None => TextValue(self.document.text_content(node))
+        }
+    }
+
+    // Collects the RDFa Lite items in the subtree of `node`, with `vocab` the vocabulary
+    // inherited from its ancestors.
+    fn rdfa(&self, node: NodeId, vocab: Option<String>, state: &mut RdfaState) {
+        let tag = match self.document.tag(node) {
+            Some(tag) => tag,
+            None => {
+                for child in self.document.children(node) {
+                    self.rdfa(child, vocab.clone(), state);
+                }
+                return
+            }
+        };
+        let vocab = match tag.get_attribute("vocab") {
+            Some(vocab) => {
+                let vocab = vocab.trim_chars(is_html_whitespace);
+                if vocab.is_empty() { None } else { Some(self.resolve(vocab)) }
+            }
+            None => vocab
+        };
+        let prefixes = state.prefixes.len();
+        match tag.get_attribute("prefix") {
+            Some(value) => state.prefixes.push_all_move(prefix_mappings(value)),
+            None => {}
+        }
+        let properties: Vec<String> = tag.get_attribute("property").map_or(Vec::new(), tokens)
+                                         .move_iter().map(|name| state.expand(name)).collect();
+
+        let new_item = match tag.get_attribute("typeof") {
+            Some(types) => {
+                let types = tokens(types).move_iter().map(|name| state.expand(name)).collect();
+                state.items.push(OpenItem {
+                    properties: properties.clone(),
+                    item: Item {
+                        types: types,
+                        id: tag.get_attribute("resource").map(|id| self.resolve(id)),
+                        vocab: vocab.clone(),
+                        properties: Vec::new(),
+                    },
+                });
+                true
+            }
+            None => {
+                if !properties.is_empty() {
+                    let value = self.rdfa_value(node, tag);
+                    match state.items.mut_last() {
+                        Some(open) => {
+                            for name in properties.iter() {
+                                open.item.properties.push((name.clone(), value.clone()));
+                            }
+                        }
+                        // Properties of the page itself aren't reported.
+                        None => {}
+                    }
+                }
+                false
+            }
+        };
+
+        for child in self.document.children(node) {
+            self.rdfa(child, vocab.clone(), state);
+        }
+        state.prefixes.truncate(prefixes);
+
+        if new_item {
+            let open = state.items.pop().unwrap();
+            if open.properties.is_empty() || state.items.is_empty() {
+                state.top_level_items.push(open.item);
+            } else {
+                let parent = state.items.mut_last().unwrap();
+                for name in open.properties.iter() {
+                    parent.item.properties.push((name.clone(), ItemValue(open.item.clone())));
+                }
+            }
+        }
+    }
+
+    fn rdfa_value(&self, node: NodeId, tag: &Tag) -> PropertyValue {
+        match tag.get_attribute("content") {
+            Some(content) => return TextValue(content.to_string()),
+            None => {}
+        }
+        for name in ["resource", "href", "src"].iter() {
+            match tag.get_attribute(*name) {
+                Some(value) => return UrlValue(self.resolve(value)),
+                None => {}
+            }
+        }
+        match tag.get_attribute("datetime") {
+            Some(datetime) if tag.ns == HtmlNs && tag.name.as_slice() == "time" => {
+                TextValue(datetime.to_string())
+            }
+            _ => TextValue(self.document.text_content(node))
+        }
+    }
+}
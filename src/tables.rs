@@ -0,0 +1,370 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Extraction of tables from parsed documents, following the HTML table processing model: cells
+// are laid out in a grid according to their colspan and rowspan, with rows of footers moved to
+// the end. Tables nested in cells are extracted separately, and left out of their cell's text.
+
+use dom::{Document, NodeId, TextNode};
+use hubbub::{Tag, HtmlNs, is_html_whitespace};
+
+// The limits the HTML table processing model puts on spans.
+static MAX_COLSPAN: uint = 1000;
+static MAX_ROWSPAN: uint = 65534;
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Cell {
+    pub node: NodeId,
+    // The slot of the top left corner of the cell, and how many columns and rows it spans.
+    pub x: uint,
+    pub y: uint,
+    pub width: uint,
+    pub height: uint,
+    // Whether the cell is a `th`.
+    pub header: bool,
+    // The text of the cell, with its whitespace collapsed.
+    pub text: String,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Table {
+    pub node: NodeId,
+    pub caption: Option<String>,
+    pub width: uint,
+    pub height: uint,
+    pub cells: Vec<Cell>,
+    // How many rows at the top are headers: rows of a `thead`, or holding only `th` cells.
+    pub header_rows: uint,
+    // The index in `cells` of the cell covering each slot, by row.
+    grid: Vec<Vec<Option<uint>>>,
+}
+
+struct TableBuilder<'a> {
+    document: &'a Document,
+    table: Table,
+    current_row: uint,
+    // The cells with a rowspan of 0, which grow until the end of their row group.
+    growing_cells: Vec<uint>,
+    // Whether each row is part of a `thead`.
+    head_rows: Vec<bool>,
+}
+
+// Extracts every table of a document, in tree order, nested tables included.
+pub fn tables(document: &Document) -> Vec<Table> {
+    document.descendants(document.root()).filter(|&node| is_html_element(document, node, "table"))
+                                         .map(|node| table(document, node)).collect()
+}
+
+// Extracts the table element `node`.
+pub fn table(document: &Document, node: NodeId) -> Table {
+    let mut builder = TableBuilder {
+        document: document,
+        table: Table {
+            node: node,
+            caption: None,
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+            header_rows: 0,
+            grid: Vec::new(),
+        },
+        current_row: 0,
+        growing_cells: Vec::new(),
+        head_rows: Vec::new(),
+    };
+    builder.build();
+    builder.table
+}
+
+fn is_html_element(document: &Document, node: NodeId, name: &str) -> bool {
+    document.tag(node).map_or(false, |tag| tag.ns == HtmlNs && tag.name.as_slice() == name)
+}
+
+fn html_element_name<'a>(document: &'a Document, node: NodeId) -> Option<&'a str> {
+    match document.tag(node) {
+        Some(tag) if tag.ns == HtmlNs => Some(tag.name.as_slice()),
+        _ => None
+    }
+}
+
+// Parses a span attribute as a non-negative integer, or returns `default` if it isn't one.
+fn span(tag: &Tag, name: &str, default: uint) -> uint {
+    let value = match tag.get_attribute(name) {
+        Some(value) => value.trim_left_chars(is_html_whitespace),
+        None => return default
+    };
+    let value = if value.starts_with("+") { value.slice_from(1) } else { value };
+    // Only ASCII digits count, each a byte long.
+    let digits = value.chars().take_while(|&c| c >= '0' && c <= '9').count();
+    match from_str::<uint>(value.slice_to(digits)) {
+        Some(span) => span,
+        // Too many digits to fit also means a span past the limits.
+        None if digits > 0 => MAX_ROWSPAN,
+        None => default
+    }
+}
+
+// How many rows there are from `row` on up to the next row group, for rows outside of groups.
+fn rows_from(document: &Document, row: NodeId) -> uint {
+    let mut rows = 1;
+    let mut next = document.next_sibling(row);
+    loop {
+        match next {
+            Some(sibling) => {
+                match html_element_name(document, sibling) {
+                    Some("tr") => rows += 1,
+                    Some("thead") | Some("tbody") | Some("tfoot") => return rows,
+                    _ => {}
+                }
+                next = document.next_sibling(sibling);
+            }
+            None => return rows
+        }
+    }
+}
+
+// The text of a cell with its whitespace collapsed, leaving out nested tables.
+fn cell_text(document: &Document, node: NodeId) -> String {
+    let mut text = String::new();
+    push_cell_text(document, node, &mut text);
+    let words: Vec<&str> = text.as_slice().split(is_html_whitespace).filter(|word| {
+        !word.is_empty()
+    }).collect();
+    words.connect(" ")
+}
+
+fn push_cell_text(document: &Document, node: NodeId, text: &mut String) {
+    for child in document.children(node) {
+        match document.node(child).data {
+            TextNode(ref data) => text.push_str(data.as_slice()),
+            _ => match html_element_name(document, child) {
+                Some("table") | Some("script") | Some("style") | Some("template") => {}
+                Some("br") => text.push_char(' '),
+                _ => push_cell_text(document, child, text),
+            }
+        }
+    }
+}
+
+impl<'a> TableBuilder<'a> {
+    fn build(&mut self) {
+        let document = self.document;
+        let node = self.table.node;
+        let mut footers = Vec::new();
+        // The rows left in the current run of rows outside of groups.
+        let mut rows_left = 0;
+        for child in document.children(node) {
+            match html_element_name(document, child) {
+                Some("caption") if self.table.caption.is_none() => {
+                    self.table.caption = Some(cell_text(document, child));
+                }
+                Some("tr") => {
+                    if rows_left == 0 {
+                        rows_left = rows_from(document, child);
+                    }
+                    self.process_row(child, false, rows_left);
+                    rows_left -= 1;
+                }
+                Some("thead") => self.process_row_group(child, true),
+                Some("tbody") => self.process_row_group(child, false),
+                // Footers are moved after the other rows.
+                Some("tfoot") => footers.push(child),
+                _ => {}
+            }
+        }
+        for &footer in footers.iter() {
+            self.process_row_group(footer, false);
+        }
+        self.end_row_group();
+
+        let header_rows = {
+            let table = &self.table;
+            let head_rows = &self.head_rows;
+            let header_rows = range(0, table.height).take_while(|&y| {
+                *head_rows.get(y) || range(0, table.width).all(|x| {
+                    table.cell_at(x, y).map_or(true, |cell| cell.header)
+                })
+            }).count();
+            // A table of nothing but header cells has no header.
+            if header_rows == table.height {
+                range(0, table.height).take_while(|&y| *head_rows.get(y)).count()
+            } else {
+                header_rows
+            }
+        };
+        self.table.header_rows = header_rows;
+    }
+
+    fn process_row_group(&mut self, node: NodeId, head: bool) {
+        self.end_row_group();
+        let document = self.document;
+        let mut rows_left = document.children(node).filter(|&child| {
+            is_html_element(document, child, "tr")
+        }).count();
+        for child in document.children(node) {
+            if is_html_element(document, child, "tr") {
+                self.process_row(child, head, rows_left);
+                rows_left -= 1;
+            }
+        }
+        self.end_row_group();
+    }
+
+    // Extends the cells growing downward to the current row, and moves on until the rows that
+    // cells of the group span are all processed.
+    fn end_row_group(&mut self) {
+        while self.current_row < self.table.height {
+            self.grow_cells();
+            self.current_row += 1;
+        }
+        self.growing_cells.clear();
+    }
+
+    fn grow_cells(&mut self) {
+        let y = self.current_row;
+        for &index in self.growing_cells.clone().iter() {
+            let (x, width) = {
+                let cell = self.table.cells.get_mut(index);
+                cell.height = y + 1 - cell.y;
+                (cell.x, cell.width)
+            };
+            for x in range(x, x + width) {
+                self.set_slot(x, y, index);
+            }
+        }
+    }
+
+    // `group_rows` is how many rows are left in the row group, this one included.
+    fn process_row(&mut self, node: NodeId, head: bool, group_rows: uint) {
+        if self.current_row == self.table.height {
+            let (width, height) = (self.table.width, self.table.height + 1);
+            self.resize(width, height);
+        }
+        self.grow_cells();
+        let document = self.document;
+        let y = self.current_row;
+        let mut x = 0;
+        for child in document.children(node) {
+            let header = match html_element_name(document, child) {
+                Some("td") => false,
+                Some("th") => true,
+                _ => continue
+            };
+            while x < self.table.width && self.table.grid.get(y).get(x).is_some() {
+                x += 1;
+            }
+            let tag = document.tag(child).unwrap();
+            let colspan = match span(tag, "colspan", 1) {
+                0 => 1,
+                colspan => if colspan > MAX_COLSPAN { MAX_COLSPAN } else { colspan }
+            };
+            let rowspan = span(tag, "rowspan", 1);
+            let rowspan = if rowspan > MAX_ROWSPAN { MAX_ROWSPAN } else { rowspan };
+            let grows = rowspan == 0;
+            // Like browsers, cells don't span past the rows of their row group.
+            let rowspan = if grows {
+                1
+            } else if rowspan > group_rows {
+                group_rows
+            } else {
+                rowspan
+            };
+
+            let width = if x + colspan > self.table.width { x + colspan } else { self.table.width };
+            let height = if y + rowspan > self.table.height {
+                y + rowspan
+            } else {
+                self.table.height
+            };
+            self.resize(width, height);
+
+            let index = self.table.cells.len();
+            self.table.cells.push(Cell {
+                node: child,
+                x: x,
+                y: y,
+                width: colspan,
+                height: rowspan,
+                header: header,
+                text: cell_text(document, child),
+            });
+            for cell_y in range(y, y + rowspan) {
+                for cell_x in range(x, x + colspan) {
+                    self.set_slot(cell_x, cell_y, index);
+                }
+            }
+            if grows {
+                self.growing_cells.push(index);
+            }
+            x += colspan;
+        }
+        *self.head_rows.get_mut(y) = head;
+        self.current_row += 1;
+    }
+
+    fn resize(&mut self, width: uint, height: uint) {
+        let added_columns = width - self.table.width;
+        for row in self.table.grid.mut_iter() {
+            row.grow(added_columns, &None);
+        }
+        while self.table.grid.len() < height {
+            self.table.grid.push(Vec::from_elem(width, None));
+            self.head_rows.push(false);
+        }
+        self.table.width = width;
+        self.table.height = height;
+    }
+
+    // Cells that overlap are a table model error, in which case the slot keeps the first cell.
+    fn set_slot(&mut self, x: uint, y: uint, index: uint) {
+        let slot = self.table.grid.get_mut(y).get_mut(x);
+        if slot.is_none() {
+            *slot = Some(index);
+        }
+    }
+}
+
+impl Table {
+    pub fn cell_at<'a>(&'a self, x: uint, y: uint) -> Option<&'a Cell> {
+        if x >= self.width || y >= self.height {
+            return None
+        }
+        (*self.grid.get(y).get(x)).map(|index| self.cells.get(index))
+    }
+
+    // The text of each slot by row, with the text of cells that span several slots repeated in
+    // each of them.
+    pub fn to_rows(&self) -> Vec<Vec<String>> {
+        range(0, self.height).map(|y| {
+            range(0, self.width).map(|x| {
+                self.cell_at(x, y).map_or(String::new(), |cell| cell.text.clone())
+            }).collect()
+        }).collect()
+    }
+
+    // The rows as CSV, as described in RFC 4180.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in self.to_rows().iter() {
+            let fields: Vec<String> = row.iter().map(|field| {
+                let quoted = field.as_slice().chars().any(|c| {
+                    c == ',' || c == '"' || c == '\n' || c == '\r'
+                });
+                if quoted {
+                    format!("\"{}\"", field.as_slice().replace("\"", "\"\""))
+                } else {
+                    field.clone()
+                }
+            }).collect();
+            csv.push_str(fields.connect(",").as_slice());
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+}
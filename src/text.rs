@@ -0,0 +1,300 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Rendering of arena documents as plain text, for instance for indexing: whitespace collapsed
+// as in a browser, blocks on lines of their own, bulleted list items and tab-separated table
+// cells, optionally wrapped to a width.
+
+use dom::{Document, NodeId, DocumentNode, DocumentFragmentNode, ShadowRootNode, TextNode};
+use dom::ElementNode;
+use hubbub::{Tag, HtmlNs, is_html_whitespace};
+
+// Elements whose content isn't rendered.
+static HIDDEN_ELEMENTS: &'static [&'static str] = &[
+    "head", "iframe", "noembed", "noframes", "noscript", "script", "style", "template", "title",
+];
+
+static BLOCK_ELEMENTS: &'static [&'static str] = &[
+    "address", "article", "aside", "blockquote", "body", "caption", "center", "dd", "details",
+    "dialog", "dir", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr", "html", "legend", "listing",
+    "main", "menu", "nav", "p", "plaintext", "section", "summary", "table", "tbody", "tfoot",
+    "thead", "xmp",
+];
+
+// Blocks that are also separated from what surrounds them by a blank line.
+static PARAGRAPH_ELEMENTS: &'static [&'static str] = &[
+    "blockquote", "dl", "h1", "h2", "h3", "h4", "h5", "h6", "p", "table",
+];
+
+struct Line {
+    // What the line starts with, such as a list bullet. Wrapped lines are indented by as much.
+    prefix: String,
+    text: String,
+    preformatted: bool,
+}
+
+struct Renderer {
+    lines: Vec<Line>,
+    current: Option<Line>,
+    pending_space: bool,
+    blank_line_pending: bool,
+    preformatted: uint,
+    // The item counter of each open list, or None for unordered ones.
+    lists: Vec<Option<uint>>,
+    bullet: Option<String>,
+    // The width of the bullet of each open list item, which its other lines are indented by.
+    indents: Vec<uint>,
+    cells: uint,
+}
+
+// Renders the document as text, wrapping lines longer than `width` characters if given.
+pub fn render(document: &Document, width: Option<uint>) -> String {
+    let mut renderer = Renderer {
+        lines: Vec::new(),
+        current: None,
+        pending_space: false,
+        blank_line_pending: false,
+        preformatted: 0,
+        lists: Vec::new(),
+        bullet: None,
+        indents: Vec::new(),
+        cells: 0,
+    };
+    renderer.children(document, document.root());
+    renderer.end_line(false);
+
+    let mut output = String::new();
+    for line in renderer.lines.iter() {
+        write_line(&mut output, line, width);
+    }
+    output
+}
+
+fn spaces(count: uint) -> String {
+    String::from_char(count, ' ')
+}
+
+impl Renderer {
+    fn children(&mut self, document: &Document, node: NodeId) {
+        for child in document.children(node) {
+            self.node(document, child);
+        }
+    }
+
+    fn node(&mut self, document: &Document, node: NodeId) {
+        match document.node(node).data {
+            TextNode(ref text) => self.text(text.as_slice()),
+            ElementNode(ref tag) => self.element(document, node, tag),
+            DocumentNode | DocumentFragmentNode | ShadowRootNode(_) => {
+                self.children(document, node)
+            }
+            _ => {}
+        }
+    }
+
+    fn element(&mut self, document: &Document, node: NodeId, tag: &Tag) {
+        if tag.is_html_element_in(HIDDEN_ELEMENTS) {
+            return
+        }
+        if tag.ns != HtmlNs {
+            return self.children(document, node)
+        }
+        match tag.name.as_slice() {
+            "br" => self.end_line(true),
+            "li" => {
+                self.end_block(false);
+                let depth = if self.lists.is_empty() { 0 } else { self.lists.len() - 1 };
+                let bullet = match self.lists.mut_last() {
+                    Some(list) => match *list {
+                        Some(ref mut counter) => {
+                            *counter += 1;
+                            format!("{}. ", *counter - 1)
+                        }
+                        None => "* ".to_string(),
+                    },
+                    None => "* ".to_string(),
+                };
+                let bullet = format!("{}{}", spaces(depth * 2), bullet);
+                self.indents.push(bullet.as_slice().char_len());
+                self.bullet = Some(bullet);
+                self.children(document, node);
+                self.end_block(false);
+                self.bullet = None;
+                self.indents.pop();
+            }
+            "ul" | "ol" => {
+                let paragraph = self.lists.is_empty();
+                self.end_block(paragraph);
+                let counter = if tag.name.as_slice() == "ol" {
+                    let start = tag.get_attribute("start").and_then(|start| {
+                        from_str::<uint>(start.trim_chars(is_html_whitespace))
+                    });
+                    Some(start.unwrap_or(1))
+                } else {
+                    None
+                };
+                self.lists.push(counter);
+                self.children(document, node);
+                self.lists.pop();
+                self.end_block(paragraph);
+            }
+            "tr" => {
+                self.end_block(false);
+                // Rows of a table nested in a cell don't count towards the cells of its row.
+                let cells = self.cells;
+                self.cells = 0;
+                self.children(document, node);
+                self.cells = cells;
+                self.end_block(false);
+            }
+            "td" | "th" => {
+                if self.cells > 0 {
+                    self.start_line();
+                    self.current.as_mut().unwrap().text.push_char('\t');
+                    self.pending_space = false;
+                }
+                self.cells += 1;
+                self.children(document, node);
+            }
+            "pre" => {
+                self.end_block(true);
+                self.preformatted += 1;
+                self.children(document, node);
+                self.preformatted -= 1;
+                self.end_block(true);
+            }
+            _ if tag.is_html_element_in(BLOCK_ELEMENTS) => {
+                let paragraph = tag.is_html_element_in(PARAGRAPH_ELEMENTS);
+                self.end_block(paragraph);
+                self.children(document, node);
+                self.end_block(paragraph);
+            }
+            _ => self.children(document, node),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.preformatted > 0 {
+            for (index, piece) in text.split('\n').enumerate() {
+                if index > 0 {
+                    self.end_line(true);
+                }
+                if !piece.is_empty() {
+                    self.start_line();
+                    self.current.as_mut().unwrap().text.push_str(piece);
+                }
+            }
+            return
+        }
+        for c in text.chars() {
+            if is_html_whitespace(c) {
+                // Whitespace at the start of a line is dropped.
+                self.pending_space = self.current.as_ref().map_or(false, |line| {
+                    !line.text.is_empty() && !line.text.as_slice().ends_with("\t")
+                });
+                continue
+            }
+            self.start_line();
+            let line = self.current.as_mut().unwrap();
+            if self.pending_space {
+                line.text.push_char(' ');
+                self.pending_space = false;
+            }
+            line.text.push_char(c);
+        }
+    }
+
+    fn start_line(&mut self) {
+        if self.current.is_some() {
+            return
+        }
+        if self.blank_line_pending && !self.lines.is_empty() {
+            self.lines.push(Line {
+                prefix: String::new(),
+                text: String::new(),
+                preformatted: false,
+            });
+        }
+        self.blank_line_pending = false;
+        // Lines in a list item other than its first are indented like its text.
+        let prefix = match self.bullet.take() {
+            Some(bullet) => bullet,
+            None => match self.indents.last() {
+                Some(&indent) => spaces(indent),
+                None if !self.lists.is_empty() => spaces(self.lists.len() * 2),
+                None => String::new(),
+            }
+        };
+        self.current = Some(Line {
+            prefix: prefix,
+            text: String::new(),
+            preformatted: self.preformatted > 0,
+        });
+    }
+
+    // Ends the current line. If `force` is set, as for `<br>`, an empty line is output too.
+    fn end_line(&mut self, force: bool) {
+        if force {
+            self.start_line();
+        }
+        match self.current.take() {
+            Some(line) => self.lines.push(line),
+            None => {}
+        }
+        self.pending_space = false;
+    }
+
+    fn end_block(&mut self, paragraph: bool) {
+        self.end_line(false);
+        if paragraph {
+            self.blank_line_pending = true;
+        }
+    }
+}
+
+fn write_line(output: &mut String, line: &Line, width: Option<uint>) {
+    let prefix_length = line.prefix.as_slice().char_len();
+    let width = match width {
+        Some(width) if !line.preformatted && line.text.as_slice().char_len() + prefix_length >
+                       width => width,
+        _ => {
+            let mut text = line.prefix.clone();
+            text.push_str(line.text.as_slice());
+            output.push_str(text.as_slice().trim_right_chars(' '));
+            output.push_char('\n');
+            return
+        }
+    };
+    let available = if width > prefix_length + 1 { width - prefix_length } else { 1 };
+    let mut row = String::new();
+    let mut first = true;
+    for word in line.text.as_slice().split(' ') {
+        if !row.is_empty() && row.as_slice().char_len() + 1 + word.char_len() > available {
+            write_row(output, line, row.as_slice(), first);
+            first = false;
+            row = String::new();
+        }
+        if !row.is_empty() {
+            row.push_char(' ');
+        }
+        row.push_str(word);
+    }
+    write_row(output, line, row.as_slice(), first);
+}
+
+fn write_row(output: &mut String, line: &Line, row: &str, first: bool) {
+    if first {
+        output.push_str(line.prefix.as_slice());
+    } else {
+        output.push_str(spaces(line.prefix.as_slice().char_len()).as_slice());
+    }
+    output.push_str(row);
+    output.push_char('\n');
+}
@@ -0,0 +1,181 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Resolution of URL references found in attributes against a base URL, following the
+// reference resolution algorithm of RFC 3986.
+
+use std::ascii::StrAsciiExt;
+use dom::Document;
+use hubbub::{HtmlNs, is_html_whitespace};
+
+struct UrlParts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+fn split_url<'a>(url: &'a str) -> UrlParts<'a> {
+    let (rest, fragment) = match url.find('#') {
+        Some(index) => (url.slice_to(index), Some(url.slice_from(index + 1))),
+        None => (url, None)
+    };
+    let (rest, query) = match rest.find('?') {
+        Some(index) => (rest.slice_to(index), Some(rest.slice_from(index + 1))),
+        None => (rest, None)
+    };
+    let (scheme, rest) = match rest.find(':') {
+        Some(index) if is_scheme(rest.slice_to(index)) => {
+            (Some(rest.slice_to(index)), rest.slice_from(index + 1))
+        }
+        _ => (None, rest)
+    };
+    let (authority, path) = if rest.starts_with("//") {
+        let rest = rest.slice_from(2);
+        match rest.find('/') {
+            Some(index) => (Some(rest.slice_to(index)), rest.slice_from(index)),
+            None => (Some(rest), "")
+        }
+    } else {
+        (None, rest)
+    };
+    UrlParts {
+        scheme: scheme,
+        authority: authority,
+        path: path,
+        query: query,
+        fragment: fragment,
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() && c.is_ascii() => {}
+        _ => return false
+    }
+    chars.all(|c| c.is_ascii() && (c.is_alphanumeric() || c == '+' || c == '-' || c == '.'))
+}
+
+// Removes the "." and ".." segments of a path.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let absolute = path.starts_with("/");
+    let mut parts: Vec<&str> = path.split('/').collect();
+    if absolute {
+        parts.remove(0);
+    }
+    let last = parts.len();
+    for (index, &segment) in parts.iter().enumerate() {
+        match segment {
+            "." => {}
+            ".." => { segments.pop(); }
+            _ => segments.push(segment),
+        }
+        // A trailing "." or ".." still denotes a directory.
+        if index + 1 == last && (segment == "." || segment == "..") {
+            segments.push("");
+        }
+    }
+    let mut result = if absolute { "/".to_string() } else { String::new() };
+    result.push_str(segments.connect("/").as_slice());
+    result
+}
+
+// The part of a base path up to and including its last slash.
+fn merge_paths(base: &UrlParts, reference_path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        return format!("/{}", reference_path)
+    }
+    match base.path.rfind('/') {
+        Some(index) => format!("{}{}", base.path.slice_to(index + 1), reference_path),
+        None => reference_path.to_string()
+    }
+}
+
+// Strips the whitespace that HTML allows around URLs in attributes, and the tabs and newlines
+// that URL parsers ignore inside them.
+pub fn clean_url(url: &str) -> String {
+    url.trim_chars(is_html_whitespace).chars().filter(|&c| {
+        c != '\t' && c != '\n' && c != '\r'
+    }).collect()
+}
+
+// Resolves `reference` against the absolute URL `base`. Returns None if the reference is
+// relative and the base has no scheme.
+pub fn resolve(base: &str, reference: &str) -> Option<String> {
+    let reference = clean_url(reference);
+    let r = split_url(reference.as_slice());
+    let base = clean_url(base);
+    let b = split_url(base.as_slice());
+
+    let (scheme, authority, path, query) = match r.scheme {
+        Some(scheme) => {
+            (scheme.to_ascii_lower(), r.authority, remove_dot_segments(r.path), r.query)
+        }
+        None => {
+            let scheme = match b.scheme {
+                Some(scheme) => scheme.to_ascii_lower(),
+                None => return None
+            };
+            if r.authority.is_some() {
+                (scheme, r.authority, remove_dot_segments(r.path), r.query)
+            } else if r.path.is_empty() {
+                (scheme, b.authority, b.path.to_string(),
+                 if r.query.is_some() { r.query } else { b.query })
+            } else if r.path.starts_with("/") {
+                (scheme, b.authority, remove_dot_segments(r.path), r.query)
+            } else {
+                (scheme, b.authority, remove_dot_segments(merge_paths(&b, r.path).as_slice()),
+                 r.query)
+            }
+        }
+    };
+
+    let mut result = scheme;
+    result.push_char(':');
+    match authority {
+        Some(authority) => {
+            result.push_str("//");
+            result.push_str(authority);
+        }
+        None => {}
+    }
+    result.push_str(path.as_slice());
+    match query {
+        Some(query) => {
+            result.push_char('?');
+            result.push_str(query);
+        }
+        None => {}
+    }
+    match r.fragment {
+        Some(fragment) => {
+            result.push_char('#');
+            result.push_str(fragment);
+        }
+        None => {}
+    }
+    Some(result)
+}
+
+// The base URL of a parsed document: the href of its first `base` element that has one,
+// resolved against `document_url`, or `document_url` itself.
+pub fn document_base_url(document: &Document, document_url: &str) -> String {
+    let href = document.descendants(document.root()).filter_map(|node| {
+        document.tag(node)
+    }).find(|tag| {
+        tag.ns == HtmlNs && tag.name.as_slice() == "base" && tag.has_attribute("href")
+    }).and_then(|tag| tag.get_attribute("href"));
+    match href.and_then(|href| resolve(document_url, href)) {
+        Some(url) => url,
+        None => document_url.to_string()
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the order in which the parser reports finished elements, in the style of the html5lib
+// tree construction tests: each case lists the elements as they finish, with their text content
+// at that point.
+
+extern crate hubbub;
+
+use std::cell::RefCell;
+use hubbub::dom;
+use hubbub::dom::{Document, NodeId};
+use hubbub::hubbub::{Parser, TreeObserver, NodeDataPtr, observe_tree};
+
+struct FinishRecorder<'a> {
+    document: &'a RefCell<Document>,
+    events: Vec<String>,
+}
+
+impl<'a> TreeObserver for FinishRecorder<'a> {
+    fn element_finished(&mut self, element: NodeDataPtr) {
+        let document = self.document.borrow();
+        let node = NodeId::from_node_ptr(element);
+        let name = document.tag(node).unwrap().name.clone();
+        self.events.push(format!("{}:{}", name, document.text_content(node)));
+    }
+}
+
+fn finished_elements(data: &str) -> Vec<String> {
+    let document = RefCell::new(Document::new());
+    let recorder = RefCell::new(FinishRecorder {
+        document: &document,
+        events: Vec::new(),
+    });
+    dom::with_tree_handler(&document, |tree_handler| {
+        observe_tree(tree_handler, &recorder, |handler| {
+            let root = document.borrow().root().to_node_ptr();
+            let mut parser = Parser::new("UTF-8", true);
+            parser.set_tree_handler(handler);
+            parser.set_document_node(root);
+            parser.parse_chunk(data.as_bytes());
+            parser.completed();
+        })
+    });
+    recorder.unwrap().events
+}
+
+fn check(data: &str, expected: &[&str]) {
+    let expected: Vec<String> = expected.iter().map(|event| event.to_string()).collect();
+    assert_eq!(finished_elements(data), expected);
+}
+
+#[test]
+fn well_formed() {
+    check("<p>a<b>b</b></p><p>c",
+          &["head:", "b:b", "p:ab", "p:c", "body:abc", "html:abc"]);
+}
+
+#[test]
+fn implied_end_tags() {
+    check("<ul><li>a<li>b</ul>c",
+          &["head:", "li:a", "li:b", "ul:ab", "body:abc", "html:abc"]);
+}
+
+// The adoption agency algorithm moves the paragraph out of the `b`, which is finished, while
+// the paragraph stays open and gets a clone of the `b`.
+#[test]
+fn misnested_formatting() {
+    check("<b><p>x</b>y",
+          &["head:", "b:", "b:x", "p:xy", "body:xy", "html:xy"]);
+}
+
+// The `i` is replaced by a clone on the stack of open elements, which the paragraph moves into.
+#[test]
+fn misnested_formatting_with_clones() {
+    check("<b><i><p>x</b>y",
+          &["head:", "i:", "b:", "b:x", "p:xy", "i:xy", "body:xy", "html:xy"]);
+}
+
+// Foster parented content goes before the table, which stays open.
+#[test]
+fn foster_parenting() {
+    check("<table>x<div>y</div><tr><td>z</table>",
+          &["head:", "div:y", "td:z", "tr:z", "tbody:z", "table:z", "body:xyz", "html:xyz"]);
+}
+
+#[test]
+fn foster_parented_formatting() {
+    check("<table><b>x<tr><td>y</table>",
+          &["head:", "b:x", "td:y", "tr:y", "tbody:y", "table:y", "body:xy", "html:xy"]);
+}
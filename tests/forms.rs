@@ -0,0 +1,86 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the form model: the controls each form owns, with their types and default values.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::forms;
+use hubbub::forms::{Form, Control};
+
+fn parse_forms(data: &str) -> Vec<Form> {
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    forms::forms(&document, "http://example.com/page")
+}
+
+// A control as "type name=default_value", with " checked" for checked ones.
+fn describe(control: &Control) -> String {
+    let mut description = format!("{} {}={}", control.control_type,
+                                  control.name.clone().unwrap_or(String::new()),
+                                  control.default_value.clone().unwrap_or("-".to_string()));
+    if control.checked {
+        description.push_str(" checked");
+    }
+    description
+}
+
+fn controls(form: &Form) -> Vec<String> {
+    form.controls.iter().map(|control| describe(control)).collect()
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[test]
+fn controls_and_default_values() {
+    let forms = parse_forms("<form name=f action=\"/submit\" method=POST enctype=bogus>\
+                             <input name=q><input type=CHECKBOX name=c checked>\
+                             <input type=weird name=w value=1><button name=b>Go</button>\
+                             <textarea name=t>hi</textarea><select name=s><option>a\
+                             <option selected>b<option selected value=c>C</select>\
+                             <select name=m multiple><optgroup disabled><option>x</optgroup>\
+                             <option selected>y</select></form>");
+    assert_eq!(forms.len(), 1);
+    let form = forms.get(0);
+    assert_eq!(form.name, Some("f".to_string()));
+    assert_eq!(form.action, "http://example.com/submit".to_string());
+    assert_eq!(form.method, "post".to_string());
+    assert_eq!(form.enctype, "application/x-www-form-urlencoded".to_string());
+    assert_eq!(controls(form), strings(&["text q=-", "checkbox c=on checked", "text w=1",
+                                         "submit b=-", "textarea t=hi", "select-one s=c",
+                                         "select-multiple m=y"]));
+    let options: Vec<(bool, bool)> = form.controls.get(5).options.iter().map(|option| {
+        (option.selected, option.disabled)
+    }).collect();
+    assert_eq!(options, vec!((false, false), (false, false), (true, false)));
+    assert!(form.controls.get(6).options.get(0).disabled);
+}
+
+#[test]
+fn select_one_without_selected_options() {
+    let forms = parse_forms("<form><select name=s><option disabled>a<option>b</select></form>");
+    assert_eq!(controls(forms.get(0)), strings(&["select-one s=b"]));
+}
+
+#[test]
+fn form_attribute() {
+    let forms = parse_forms("<form id=one></form><form id=two><input name=a form=one>\
+                             <input name=b form=missing><input name=c></form>");
+    assert_eq!(controls(forms.get(0)), strings(&["text a=-"]));
+    assert_eq!(controls(forms.get(1)), strings(&["text c=-"]));
+}
+
+#[test]
+fn parser_association() {
+    let forms = parse_forms("<table><form><tr><td><input name=x></table>");
+    assert_eq!(forms.get(0).action, "http://example.com/page".to_string());
+    assert_eq!(controls(forms.get(0)), strings(&["text x=-"]));
+}
@@ -0,0 +1,62 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the inventory of references, as "element attribute raw -> url".
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::links::references;
+
+fn inventory(data: &str, document_url: &str) -> Vec<String> {
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    references(&document, document_url).iter().map(|reference| {
+        format!("{} {} {} -> {}", reference.element, reference.attribute, reference.raw,
+                reference.url)
+    }).collect()
+}
+
+fn check(data: &str, document_url: &str, expected: &[&str]) {
+    let expected: Vec<String> = expected.iter().map(|line| line.to_string()).collect();
+    assert_eq!(inventory(data, document_url), expected);
+}
+
+#[test]
+fn attributes() {
+    check("<a href=\"page\">a</a><img src=\"../i.png\"><form action=\"?q\"></form>\
+           <svg><a xlink:href=\"/s\"></a></svg>",
+          "http://example.com/dir/doc.html",
+          &["a href page -> http://example.com/dir/page",
+            "img src ../i.png -> http://example.com/i.png",
+            "form action ?q -> http://example.com/dir/doc.html?q",
+            "a xlink:href /s -> http://example.com/s"]);
+}
+
+#[test]
+fn base_url() {
+    check("<base href=\"http://other.example/sub/\"><base href=\"/ignored/\"><a href=x>x</a>",
+          "http://example.com/", &["a href x -> http://other.example/sub/x"]);
+}
+
+#[test]
+fn srcset_and_styles() {
+    check("<img srcset=\"a.png 1x, b.png 2x,c.png\"><div style=\"background: URL( 'd.png' ); \
+           mask: url(e.png)\"></div>",
+          "http://example.com/",
+          &["img srcset a.png -> http://example.com/a.png",
+            "img srcset b.png -> http://example.com/b.png",
+            "img srcset c.png -> http://example.com/c.png",
+            "div style d.png -> http://example.com/d.png",
+            "div style e.png -> http://example.com/e.png"]);
+}
+
+#[test]
+fn unresolvable_urls_are_kept_as_written() {
+    check("<a href=\" page \">a</a>", "", &["a href  page  -> page"]);
+}
@@ -0,0 +1,61 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the conversion of parsed documents to Markdown.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::markdown::{to_markdown, UnmappedPolicy, KeepAsHtml, KeepContent, DropContent};
+
+fn convert(data: &str, policy: UnmappedPolicy) -> String {
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    to_markdown(&document, document.root(), policy)
+}
+
+#[test]
+fn headings_and_inline_content() {
+    assert_eq!(convert("<title>T</title><h2>Title</h2><p>Some <em>em</em> and <strong>bold\
+                        </strong> <a href=\"/x\" title=\"t\">link</a>.</p>", KeepAsHtml),
+               "## Title\n\nSome *em* and **bold** [link](/x \"t\").\n".to_string());
+}
+
+#[test]
+fn lists() {
+    assert_eq!(convert("<ul><li>a<li>b<ol start=3><li>c</ol></ul>", KeepAsHtml),
+               "- a\n- b\n  3. c\n".to_string());
+}
+
+#[test]
+fn escaping() {
+    assert_eq!(convert("<p>1. not a list *star* [x]</p><p>- not an item</p>", KeepAsHtml),
+               "1\\. not a list \\*star\\* \\[x\\]\n\n\\- not an item\n".to_string());
+}
+
+#[test]
+fn code() {
+    assert_eq!(convert("<pre><code class=\"language-rust\">fn main() {}\n</code></pre>",
+                       KeepAsHtml),
+               "```rust\nfn main() {}\n```\n".to_string());
+    assert_eq!(convert("<p>a <code>b`c</code></p>", KeepAsHtml), "a ``b`c``\n".to_string());
+}
+
+#[test]
+fn tables() {
+    assert_eq!(convert("<table><tr><th>A<th>B<tr><td>1<td>2</table>", KeepAsHtml),
+               "| A | B |\n| --- | --- |\n| 1 | 2 |\n".to_string());
+}
+
+#[test]
+fn unmapped_elements() {
+    let data = "<p>a <sup>2</sup></p>";
+    assert_eq!(convert(data, KeepAsHtml), "a <sup>2</sup>\n".to_string());
+    assert_eq!(convert(data, KeepContent), "a 2\n".to_string());
+    assert_eq!(convert(data, DropContent), "a\n".to_string());
+}
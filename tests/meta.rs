@@ -0,0 +1,70 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the extraction of page metadata.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::meta;
+use hubbub::meta::Link;
+
+static HEAD: &'static str = "<html lang=en><head><title> A  page </title>\
+    <meta name=description content=\" Desc \"><meta name=keywords content=\"a, b,,c\">\
+    <meta property=\"og:title\" content=OG><meta name=\"twitter:card\" content=summary>\
+    <meta itemprop=description content=item>\
+    <link rel=canonical href=\"/c\"><link rel=icon href=\"/i.png\" sizes=16x16>\
+    <link rel=alternate hreflang=fr href=\"/fr\"><link rel=\"alternate stylesheet\" \
+    href=\"/s.css\"></head>";
+
+fn link(rel: &str, href: &str) -> Link {
+    Link {
+        rel: vec!(rel.to_string()),
+        href: href.to_string(),
+        link_type: None,
+        hreflang: None,
+        media: None,
+        sizes: None,
+        title: None,
+    }
+}
+
+#[test]
+fn head_metadata() {
+    let metadata = meta::extract(HEAD.as_bytes(), "UTF-8");
+    assert_eq!(metadata.title, Some("A page".to_string()));
+    assert_eq!(metadata.description, Some("Desc".to_string()));
+    assert_eq!(metadata.keywords, vec!("a".to_string(), "b".to_string(), "c".to_string()));
+    assert_eq!(metadata.canonical, Some("/c".to_string()));
+    assert_eq!(metadata.icons, vec!(Link { sizes: Some("16x16".to_string()),
+                                           ..link("icon", "/i.png") }));
+    assert_eq!(metadata.alternates, vec!(Link { hreflang: Some("fr".to_string()),
+                                                ..link("alternate", "/fr") }));
+    assert_eq!(metadata.open_graph, vec!(("og:title".to_string(), "OG".to_string())));
+    assert_eq!(metadata.twitter, vec!(("twitter:card".to_string(), "summary".to_string())));
+    assert_eq!(metadata.lang, Some("en".to_string()));
+}
+
+#[test]
+fn parsing_stops_at_the_body() {
+    let mut data = HEAD.to_string();
+    data.push_str("<body>");
+    data.push_str(String::from_char(4096, 'x').as_slice());
+    data.push_str("<meta property=\"og:late\" content=x>");
+    let metadata = meta::extract(data.as_bytes(), "UTF-8");
+    assert_eq!(metadata.open_graph, vec!(("og:title".to_string(), "OG".to_string())));
+}
+
+#[test]
+fn declared_charset() {
+    let document = dom::parse("<meta http-equiv=Content-Type content=\"text/html; \
+                               charset='iso-8859-1'\">".as_bytes(), "UTF-8");
+    let metadata = meta::from_document(&document, document.root());
+    assert_eq!(metadata.charset, Some("iso-8859-1".to_string()));
+}
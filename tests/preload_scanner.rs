@@ -0,0 +1,55 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks that the preload scanner finds the same resources however its input is split.
+
+extern crate hubbub;
+
+use hubbub::hubbub::{PreloadScanner, PreloadRequest, ResourceType, ScriptResource, ImageResource};
+
+fn scan(parts: &[&str]) -> Vec<PreloadRequest> {
+    let mut scanner = PreloadScanner::new("http://example.com/dir/page.html");
+    let mut requests = Vec::new();
+    for part in parts.iter() {
+        requests.push_all_move(scanner.scan(part.as_bytes()));
+    }
+    requests
+}
+
+fn request(url: &str, resource_type: ResourceType) -> PreloadRequest {
+    PreloadRequest {
+        url: url.to_string(),
+        resource_type: resource_type,
+    }
+}
+
+#[test]
+fn tag_split_across_parts() {
+    assert_eq!(scan(&["<p>a<img sr", "c=a.png>b"]),
+               vec!(request("http://example.com/dir/a.png", ImageResource)));
+}
+
+#[test]
+fn script_split_across_parts() {
+    assert_eq!(scan(&["<script>var a = '<img src=a.png>", "<img src=b.png>';</scr",
+                      "ipt><script src=c.js></script>"]),
+               vec!(request("http://example.com/dir/c.js", ScriptResource)));
+}
+
+#[test]
+fn comment_split_across_parts() {
+    assert_eq!(scan(&["<!-- <img src=a.png>", " <img src=b.png> -->", "<img src=c.png>"]),
+               vec!(request("http://example.com/dir/c.png", ImageResource)));
+}
+
+#[test]
+fn template_contents_are_skipped() {
+    assert_eq!(scan(&["<template><img src=a.png>", "</template><img src=b.png>"]),
+               vec!(request("http://example.com/dir/b.png", ImageResource)));
+}
@@ -0,0 +1,125 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the streaming rewriter: input that no handler touches comes out byte for byte, and
+// markup added around or in place of elements ends up where their end tags are, written or
+// implied.
+
+extern crate hubbub;
+
+use std::io::{BufReader, MemWriter};
+use hubbub::rewrite::{Rewriter, Element, ElementHandler, Comment, CommentHandler};
+
+enum Action {
+    Remove,
+    Append,
+    InnerContent,
+}
+
+struct Handler {
+    action: Action,
+}
+
+impl ElementHandler for Handler {
+    fn element(&mut self, element: &mut Element) {
+        match self.action {
+            Remove => element.remove(),
+            Append => element.append("!"),
+            InnerContent => element.set_inner_content("[x]"),
+        }
+    }
+}
+
+fn rewrite(data: &str, selector: &str, action: Action) -> String {
+    let mut handler = Handler { action: action };
+    let mut output = MemWriter::new();
+    {
+        let mut rewriter = Rewriter::new();
+        rewriter.on_element(selector, &mut handler);
+        rewriter.run(&mut BufReader::new(data.as_bytes()), &mut output).unwrap();
+    }
+    String::from_utf8(output.unwrap()).unwrap()
+}
+
+#[test]
+fn unchanged_input_round_trips() {
+    let data = "<!DOCTYPE html><html><HEAD><title>a &amp; b</title></head>\n\
+                <body class='x' data-y=\"1\"><p>text <b>bold<br/></b></p><!-- c -->\
+                <script>if (a < b) {}</script></body></html>";
+    assert_eq!(rewrite(data, "span", Remove), data.to_string());
+}
+
+#[test]
+fn removed_paragraph_ends_at_next_paragraph() {
+    assert_eq!(rewrite("<p class=x>a<p>b<p>c", "p.x", Remove), "<p>b<p>c".to_string());
+    assert_eq!(rewrite("<p class=x>a<div>b</div>", "p.x", Remove), "<div>b</div>".to_string());
+}
+
+#[test]
+fn removed_list_item_ends_at_next_item() {
+    assert_eq!(rewrite("<ul><li class=x>a<ul><li>b<li>c</ul><li>d</ul>", "li.x", Remove),
+               "<ul><li>d</ul>".to_string());
+    assert_eq!(rewrite("<ul><li class=x>a</ul>b", "li.x", Remove), "<ul></ul>b".to_string());
+}
+
+#[test]
+fn removed_cell_ends_at_next_cell() {
+    assert_eq!(rewrite("<table><tr><td class=x>a<td>b<tr><td>c</table>", "td.x", Remove),
+               "<table><tr><td>b<tr><td>c</table>".to_string());
+}
+
+#[test]
+fn nested_elements_with_the_same_name() {
+    assert_eq!(rewrite("<div class=x><div>a</div>b</div>c", "div.x", Remove), "c".to_string());
+    assert_eq!(rewrite("<div class=x><div>a</div>b</div>c", "div.x", InnerContent),
+               "<div class=x>[x]</div>c".to_string());
+}
+
+#[test]
+fn appended_markup_goes_before_implied_end() {
+    assert_eq!(rewrite("<dl><dt>a<dd>b</dl>", "dt", Append), "<dl><dt>a!<dd>b</dl>".to_string());
+    assert_eq!(rewrite("<p>a<p>b", "p", Append), "<p>a!<p>b!".to_string());
+}
+
+#[test]
+fn self_closing_html_elements_are_not_void() {
+    assert_eq!(rewrite("<div class=\"x\"/>a</div>b", "div.x", Remove), "b".to_string());
+}
+
+struct CommentSetter {
+    text: &'static str,
+    accepted: Option<bool>,
+}
+
+impl CommentHandler for CommentSetter {
+    fn comment(&mut self, comment: &mut Comment) {
+        self.accepted = Some(comment.set_text(self.text));
+    }
+}
+
+fn set_comment_text(data: &str, text: &'static str) -> (String, Option<bool>) {
+    let mut handler = CommentSetter { text: text, accepted: None };
+    let mut output = MemWriter::new();
+    {
+        let mut rewriter = Rewriter::new();
+        rewriter.on_comment(&mut handler);
+        rewriter.run(&mut BufReader::new(data.as_bytes()), &mut output).unwrap();
+    }
+    (String::from_utf8(output.unwrap()).unwrap(), handler.accepted)
+}
+
+// Text that would end the comment early is rejected, so that it can't inject markup.
+#[test]
+fn comment_text_cannot_end_comment() {
+    assert_eq!(set_comment_text("<!--a-->b", " c "), ("<!-- c -->b".to_string(), Some(true)));
+    for text in ["--><script>x</script>", "--!><img src=x onerror=y>", ">c", "->c",
+                 "a<!--b", "a<!-"].iter() {
+        assert_eq!(set_comment_text("<!--a-->b", *text), ("<!--a-->b".to_string(), Some(false)));
+    }
+}
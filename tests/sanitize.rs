@@ -0,0 +1,122 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the sanitizer against known bypass payloads: the output is parsed again as a browser
+// would, and must hold no scripts, styles or event handler attributes, and sanitize to itself.
+
+extern crate hubbub;
+
+use std::ascii::StrAsciiExt;
+use hubbub::dom;
+use hubbub::hubbub::{Tag, HtmlNs, SvgNs};
+use hubbub::sanitize::{Policy, sanitize};
+use hubbub::serialize::attribute_name;
+
+fn body() -> Tag {
+    Tag {
+        ns: HtmlNs,
+        name: "body".to_string(),
+        attributes: Vec::new(),
+        self_closing: false,
+    }
+}
+
+fn check_safe(data: &str, policy: &Policy) {
+    let output = sanitize(data, policy);
+    let document = dom::parse_fragment(&body(), output.as_bytes());
+    for node in document.descendants(document.root()) {
+        let tag = match document.tag(node) {
+            Some(tag) => tag,
+            None => continue
+        };
+        assert!(!["script", "style", "noscript", "iframe", "xmp", "noembed", "noframes",
+                  "plaintext"].iter().any(|name| tag.name.as_slice() == *name),
+                "{} in {}", tag.name, output);
+        for attribute in tag.attributes.iter() {
+            let name = attribute_name(attribute).to_ascii_lower();
+            assert!(!name.as_slice().starts_with("on"), "{} in {}", name, output);
+        }
+    }
+    assert_eq!(sanitize(output.as_slice(), policy), output);
+}
+
+fn foreign_policy() -> Policy {
+    let mut policy = Policy::basic();
+    policy.allow_element(SvgNs, "svg");
+    policy.allow_element(SvgNs, "style");
+    policy.allow_element(HtmlNs, "noscript");
+    policy.allow_element(HtmlNs, "xmp");
+    policy.allow_element(HtmlNs, "style");
+    policy.allow_comments(true);
+    policy
+}
+
+#[test]
+fn noscript_attribute() {
+    let payload = "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\">";
+    check_safe(payload, &Policy::basic());
+    check_safe(payload, &foreign_policy());
+}
+
+#[test]
+fn raw_text_elements_are_removed() {
+    let policy = foreign_policy();
+    assert_eq!(sanitize("a<noscript><b>b</b></noscript>c", &policy), "ac".to_string());
+    assert_eq!(sanitize("a<xmp><b>b</b></xmp>c", &policy), "ac".to_string());
+}
+
+#[test]
+fn svg_style() {
+    let payload = "<svg><style><img src=x onerror=alert(1)></style></svg>";
+    check_safe(payload, &Policy::basic());
+    check_safe(payload, &foreign_policy());
+    check_safe("<svg></p><style><a id=\"</style><img src=1 onerror=alert(1)>\">",
+               &foreign_policy());
+}
+
+#[test]
+fn mathml_table_mglyph() {
+    let payload = "<math><mtext><table><mglyph><style><img src=x onerror=alert(1)>";
+    check_safe(payload, &Policy::basic());
+    check_safe(payload, &foreign_policy());
+    check_safe("<math><mtext><table><mglyph><style><!--</style><img title=\"--&gt;&lt;/mglyph&gt;\
+                &lt;img&Tab;src=1&Tab;onerror=alert(1)&gt;\">",
+               &foreign_policy());
+}
+
+#[test]
+fn markup_in_attribute_values_is_escaped() {
+    assert_eq!(sanitize("<p title=\"</p><img src=x onerror=alert(1)>\">x</p>", &Policy::basic()),
+               "<p title=\"&lt;/p&gt;&lt;img src=x onerror=alert(1)&gt;\">x</p>".to_string());
+}
+
+#[test]
+fn event_handlers_are_dropped() {
+    let mut policy = Policy::basic();
+    policy.allow_attribute("*", "onclick");
+    check_safe("<p onclick=alert(1) ONMOUSEOVER=alert(1)>x</p>", &policy);
+}
+
+#[test]
+fn foreign_attribute_names_keep_their_case() {
+    let mut policy = Policy::new();
+    policy.allow_element(SvgNs, "svg");
+    policy.allow_attribute("svg", "viewBox");
+    policy.allow_element(HtmlNs, "p");
+    policy.allow_attribute("p", "TITLE");
+    let output = sanitize("<svg viewbox=\"0 0 1 1\" width=1></svg><p title=x>", &policy);
+    assert_eq!(output, "<svg viewBox=\"0 0 1 1\"></svg><p title=\"x\"></p>".to_string());
+}
+
+#[test]
+fn javascript_urls_are_dropped() {
+    check_safe("<a href=\" java\tscript:alert(1)\">x</a>", &Policy::basic());
+    assert_eq!(sanitize("<a href=\"jav&#x09;ascript:alert(1)\">x</a>", &Policy::basic()),
+               "<a>x</a>".to_string());
+}
@@ -0,0 +1,145 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks what the parser passes to a script runner, and how it carries on after each of the
+// actions the runner can return.
+
+extern crate hubbub;
+
+use std::cell::RefCell;
+use hubbub::dom;
+use hubbub::dom::Document;
+use hubbub::hubbub::{Parser, NodeDataPtr, Attribute, ScriptRunner, ScriptAction};
+use hubbub::hubbub::{ContinueParsing, PauseParsing, WriteMarkup};
+use hubbub::serialize;
+use hubbub::serialize::ChildrenOnly;
+
+// Returns the given actions in turn, then carries on, and records the attributes and source of
+// each script it runs.
+struct TestRunner {
+    actions: Vec<ScriptAction>,
+    scripts: Vec<(Vec<String>, String)>,
+}
+
+impl TestRunner {
+    fn new(actions: Vec<ScriptAction>) -> TestRunner {
+        TestRunner {
+            actions: actions,
+            scripts: Vec::new(),
+        }
+    }
+}
+
+impl ScriptRunner for TestRunner {
+    fn run_script(&mut self, _script: NodeDataPtr, attributes: &[Attribute], source: &str)
+                  -> ScriptAction {
+        let attributes = attributes.iter().map(|attribute| {
+            format!("{}={}", attribute.name, attribute.value)
+        }).collect();
+        self.scripts.push((attributes, source.to_string()));
+        self.actions.remove(0).unwrap_or(ContinueParsing)
+    }
+}
+
+fn serialized(document: &Document) -> String {
+    serialize::to_string(document, document.root(), ChildrenOnly)
+}
+
+// Parses `chunks` one after the other, and returns the serialized document.
+fn parse(chunks: &[&str], runner: &mut TestRunner) -> String {
+    let document = RefCell::new(Document::new());
+    dom::with_tree_handler(&document, |tree_handler| {
+        let root = document.borrow().root().to_node_ptr();
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_tree_handler(tree_handler);
+        parser.set_document_node(root);
+        parser.enable_scripting(true);
+        parser.set_script_runner(&mut *runner);
+        for chunk in chunks.iter() {
+            parser.parse_chunk(chunk.as_bytes());
+        }
+        parser.completed();
+    });
+    serialized(&document.unwrap())
+}
+
+#[test]
+fn continue_parsing() {
+    let mut runner = TestRunner::new(vec!(ContinueParsing));
+    assert_eq!(parse(&["<body><script>a</script><p>b</p><script>c</script>"], &mut runner)
+                   .as_slice(),
+               "<html><head></head><body><script>a</script><p>b</p><script>c</script></body>\
+                </html>");
+    assert_eq!(runner.scripts, vec!((vec!(), "a".to_string()), (vec!(), "c".to_string())));
+}
+
+#[test]
+fn pause_and_resume() {
+    let document = RefCell::new(Document::new());
+    let mut runner = TestRunner::new(vec!(PauseParsing));
+    dom::with_tree_handler(&document, |tree_handler| {
+        let root = document.borrow().root().to_node_ptr();
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_tree_handler(tree_handler);
+        parser.set_document_node(root);
+        parser.set_script_runner(&mut runner);
+        parser.parse_chunk("<body><script>a</script><p>b</p>".as_bytes());
+        assert_eq!(serialized(&*document.borrow()).as_slice(),
+                   "<html><head></head><body><script>a</script></body></html>");
+
+        // Input given while paused is buffered until the parser resumes.
+        parser.parse_chunk("<p>c</p>".as_bytes());
+        assert_eq!(serialized(&*document.borrow()).as_slice(),
+                   "<html><head></head><body><script>a</script></body></html>");
+
+        parser.resume();
+        parser.completed();
+    });
+    assert_eq!(serialized(&document.unwrap()).as_slice(),
+               "<html><head></head><body><script>a</script><p>b</p><p>c</p></body></html>");
+    assert_eq!(runner.scripts.len(), 1);
+}
+
+#[test]
+fn write_markup() {
+    let mut runner = TestRunner::new(vec!(WriteMarkup("<i>w</i>".to_string())));
+    assert_eq!(parse(&["<body><script>a</script><p>b</p>"], &mut runner).as_slice(),
+               "<html><head></head><body><script>a</script><i>w</i><p>b</p></body></html>");
+}
+
+// Markup written by a script can contain scripts of its own, which run before the rest of the
+// input is parsed.
+#[test]
+fn write_markup_with_script() {
+    let mut runner = TestRunner::new(vec!(WriteMarkup("<script>b</script>".to_string())));
+    assert_eq!(parse(&["<body><script>a</script><script>c</script>"], &mut runner).as_slice(),
+               "<html><head></head><body><script>a</script><script>b</script>\
+                <script>c</script></body></html>");
+    let sources: Vec<&str> = runner.scripts.iter().map(|&(_, ref source)| source.as_slice())
+                                   .collect();
+    assert_eq!(sources, vec!("a", "b", "c"));
+}
+
+// The source arrives in three chunks, so the parser inserts it as three pieces of text.
+#[test]
+fn source_split_across_text_nodes() {
+    let mut runner = TestRunner::new(vec!());
+    parse(&["<body><script>var a", " = 1;", "\nvar b = 2;</script>"], &mut runner);
+    assert_eq!(runner.scripts, vec!((vec!(), "var a = 1;\nvar b = 2;".to_string())));
+}
+
+#[test]
+fn attributes() {
+    let mut runner = TestRunner::new(vec!());
+    parse(&["<body><script type=module src=\"a.js\" async>b</script>"], &mut runner);
+    assert_eq!(runner.scripts,
+               vec!((vec!("type=module".to_string(), "src=a.js".to_string(),
+                          "async=".to_string()),
+                     "b".to_string())));
+}
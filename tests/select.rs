@@ -0,0 +1,96 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks selector matching against a small document, by the IDs of the elements that match.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::select;
+
+static DOCUMENT: &'static str = "<div id=a class='x y'><p id=b>one</p><p id=c class=x>two \
+                                 <span id=d>three</span></p><ul id=e><li id=f><li id=g \
+                                 class=x><li id=h><li id=i class=x><li id=j></ul><section \
+                                 id=k></section></div><p id=l></p>";
+
+fn ids(selector: &str) -> String {
+    let document = dom::parse(DOCUMENT.as_bytes(), "UTF-8");
+    let selectors = select::parse(selector).unwrap();
+    let mut ids = Vec::new();
+    for node in select::select(&document, document.root(), &selectors).move_iter() {
+        match document.tag(node).unwrap().get_attribute("id") {
+            Some(id) => ids.push(id.to_string()),
+            None => {}
+        }
+    }
+    ids.connect(" ")
+}
+
+#[test]
+fn combinators() {
+    assert_eq!(ids("div p").as_slice(), "b c");
+    assert_eq!(ids("body > p").as_slice(), "l");
+    assert_eq!(ids("ul > li").as_slice(), "f g h i j");
+    assert_eq!(ids("#b + p").as_slice(), "c");
+    assert_eq!(ids("#b ~ *").as_slice(), "c e k");
+    assert_eq!(ids("div p > span").as_slice(), "d");
+    assert_eq!(ids("#a > span").as_slice(), "");
+}
+
+#[test]
+fn compound_selectors() {
+    assert_eq!(ids(".x").as_slice(), "a c g i");
+    assert_eq!(ids("li.x").as_slice(), "g i");
+    assert_eq!(ids("[class~=y]").as_slice(), "a");
+    assert_eq!(ids("DIV#a.y").as_slice(), "a");
+    assert_eq!(ids("p, li.x").as_slice(), "b c g i l");
+}
+
+#[test]
+fn nth() {
+    assert_eq!(ids("li:nth-child(2n)").as_slice(), "g i");
+    assert_eq!(ids("li:nth-child(odd)").as_slice(), "f h j");
+    assert_eq!(ids("li:nth-child(-n+2)").as_slice(), "f g");
+    assert_eq!(ids("li:nth-last-child(1)").as_slice(), "j");
+    assert_eq!(ids("li:nth-child(2 of .x)").as_slice(), "i");
+    assert_eq!(ids("li:nth-last-child(1 of .x)").as_slice(), "i");
+    assert_eq!(ids("#a > :nth-of-type(2)").as_slice(), "c");
+    assert_eq!(ids("p:first-of-type").as_slice(), "b l");
+    assert_eq!(ids("li:only-child").as_slice(), "");
+}
+
+#[test]
+fn has() {
+    assert_eq!(ids("div:has(> ul)").as_slice(), "a");
+    assert_eq!(ids("p:has(span)").as_slice(), "c");
+    assert_eq!(ids(":has(+ ul)").as_slice(), "c");
+    assert_eq!(ids("ul:has(~ section)").as_slice(), "e");
+    assert_eq!(ids("div:has(> span)").as_slice(), "");
+}
+
+#[test]
+fn not() {
+    assert_eq!(ids("li:not(.x)").as_slice(), "f h j");
+    assert_eq!(ids("p:not(div p)").as_slice(), "l");
+    assert_eq!(ids(".x:not(li, p)").as_slice(), "a");
+    assert_eq!(ids("li:not(:nth-child(2 of .x))").as_slice(), "f g h j");
+}
+
+#[test]
+fn empty() {
+    assert_eq!(ids("[id]:empty").as_slice(), "f g h i j k l");
+}
+
+#[test]
+fn invalid_selectors() {
+    assert!(select::parse("p >").is_none());
+    assert!(select::parse("li:nth-child(2n+)").is_none());
+    assert!(select::parse(":has()").is_none());
+    assert!(select::parse("p,").is_none());
+}
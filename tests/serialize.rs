@@ -0,0 +1,126 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the serializers on parsed documents. HTML output must parse back to the same tree, so
+// serializing it again gives the same markup; XHTML output must be well-formed XML.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::dom::{Document, NodeId};
+use hubbub::serialize;
+use hubbub::serialize::ChildrenOnly;
+
+static XHTML: &'static str = "xmlns=\"http://www.w3.org/1999/xhtml\"";
+static SVG: &'static str = "xmlns=\"http://www.w3.org/2000/svg\"";
+
+// Parses `data` as the content of the body, which is returned with the document.
+fn parse_body(data: &str) -> (Document, NodeId) {
+    let data = format!("<!DOCTYPE html><body>{}", data);
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    let html = document.last_child(document.root()).unwrap();
+    let body = document.last_child(html).unwrap();
+    (document, body)
+}
+
+fn to_html(data: &str) -> String {
+    let (document, body) = parse_body(data);
+    serialize::to_string(&document, body, ChildrenOnly)
+}
+
+fn to_xhtml(data: &str) -> String {
+    let (document, body) = parse_body(data);
+    serialize::to_xhtml_string(&document, body, ChildrenOnly)
+}
+
+// Serializes `data` and checks that the output round trips.
+fn check_html(data: &str, expected: &str) {
+    let html = to_html(data);
+    assert_eq!(html.as_slice(), expected);
+    assert_eq!(to_html(html.as_slice()), html);
+}
+
+#[test]
+fn void_elements() {
+    check_html("<br/><img src=a><input type=text></input>a<hr>",
+               "<br><img src=\"a\"><input type=\"text\">a<hr>");
+    // Only HTML elements are void.
+    check_html("<svg><link/></svg>", "<svg><link></link></svg>");
+}
+
+#[test]
+fn text_escaping() {
+    check_html("a < b && c > d\u00a0e \"f\" 'g'",
+               "a &lt; b &amp;&amp; c &gt; d&nbsp;e \"f\" 'g'");
+    check_html("<p>&lt;script&gt;</p>", "<p>&lt;script&gt;</p>");
+}
+
+#[test]
+fn raw_text_is_not_escaped() {
+    check_html("<script>if (a < b && c > \"d\") {}</script><style>a > b { content: \"&\" }</style>",
+               "<script>if (a < b && c > \"d\") {}</script>\
+                <style>a > b { content: \"&\" }</style>");
+    check_html("<xmp>a <b> & c</xmp>", "<xmp>a <b> & c</xmp>");
+    // Text in foreign elements named like raw text elements is escaped.
+    check_html("<svg><style>a < b</style></svg>", "<svg><style>a &lt; b</style></svg>");
+}
+
+#[test]
+fn attribute_quoting() {
+    check_html("<p title='a \"b\" c' data-x=y class=\"\" lang=&amp;>d</p>",
+               "<p title=\"a &quot;b&quot; c\" data-x=\"y\" class=\"\" lang=\"&amp;\">d</p>");
+    // Markup in attribute values is escaped too, in case the element is parsed as raw text.
+    check_html("<noscript><p title=\"</noscript><img src=x onerror=y>\"></p></noscript>",
+               "<noscript><p title=\"&lt;/noscript&gt;&lt;img src=x onerror=y&gt;\"></p>\
+                </noscript>");
+}
+
+#[test]
+fn foreign_attribute_prefixes() {
+    check_html("<svg><a xlink:href=a xml:lang=b></a></svg>",
+               "<svg><a xlink:href=\"a\" xml:lang=\"b\"></a></svg>");
+}
+
+#[test]
+fn xhtml_void_and_empty_elements() {
+    assert_eq!(to_xhtml("<br><img src=a><p></p>"),
+               format!("<br {0} /><img {0} src=\"a\" /><p {0}></p>", XHTML));
+    assert_eq!(to_xhtml("<svg><circle></circle><g><rect/></g></svg>"),
+               format!("<svg {}><circle /><g><rect /></g></svg>", SVG));
+}
+
+#[test]
+fn xhtml_raw_text_is_escaped() {
+    assert_eq!(to_xhtml("<script>if (a < b && c > d) {}</script>"),
+               format!("<script {}>if (a &lt; b &amp;&amp; c &gt; d) {{}}</script>", XHTML));
+    // Characters that XML doesn't allow are replaced.
+    assert_eq!(to_xhtml("<style>a\x01b</style>"),
+               format!("<style {}>a\ufffdb</style>", XHTML));
+}
+
+#[test]
+fn xhtml_attribute_quoting() {
+    assert_eq!(to_xhtml("<p title='a \"b\"\t<c>\n&' class=\"\">d</p>"),
+               format!("<p {} title=\"a &quot;b&quot;&#9;&lt;c&gt;&#10;&amp;\" class=\"\">d</p>",
+                       XHTML));
+}
+
+#[test]
+fn xhtml_namespaces() {
+    assert_eq!(to_xhtml("<svg><a xlink:href=a></a></svg><p xmlns=b>c</p>"),
+               format!("<svg {}><a xmlns:xlink=\"http://www.w3.org/1999/xlink\" xlink:href=\"a\" />\
+                        </svg><p {}>c</p>", SVG, XHTML));
+}
+
+#[test]
+fn xhtml_comments_and_names() {
+    assert_eq!(to_xhtml("<!--a--b---><!---->"), "<!--a- -b- --><!---->".to_string());
+    assert_eq!(to_xhtml("<p a:b=c d\"e=f>g</p>"),
+               format!("<p {} aU0003Ab=\"c\" dU00022e=\"f\">g</p>", XHTML));
+}
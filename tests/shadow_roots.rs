@@ -0,0 +1,118 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks that declarative shadow roots are attached to their hosts, and that they are dumped,
+// serialized and cloned along with them.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::dom::Document;
+use hubbub::dump;
+use hubbub::hubbub::{Tag, HtmlNs};
+use hubbub::serialize;
+use hubbub::serialize::ChildrenOnly;
+
+fn body() -> Tag {
+    Tag {
+        ns: HtmlNs,
+        name: "body".to_string(),
+        attributes: Vec::new(),
+        self_closing: false,
+    }
+}
+
+fn parse(data: &str) -> Document {
+    dom::parse_fragment(&body(), data.as_bytes())
+}
+
+fn dump(data: &str) -> String {
+    let document = parse(data);
+    dump::to_string(&document, document.root())
+}
+
+fn round_trip(data: &str) {
+    let document = parse(data);
+    assert_eq!(serialize::to_string(&document, document.root(), ChildrenOnly).as_slice(), data);
+}
+
+#[test]
+fn open_and_closed_modes() {
+    assert_eq!(dump("<div><template shadowrootmode=open><b>a</b></template>c</div>").as_slice(),
+               "| <div>\n\
+                |   #shadow-root (open)\n\
+                |     <b>\n\
+                |       \"a\"\n\
+                |   \"c\"\n");
+    assert_eq!(dump("<div><template shadowrootmode=closed>a</template></div>").as_slice(),
+               "| <div>\n\
+                |   #shadow-root (closed)\n\
+                |     \"a\"\n");
+    round_trip("<div><template shadowrootmode=\"open\"><b>a</b></template>c</div>");
+    round_trip("<div><template shadowrootmode=\"closed\">a</template></div>");
+}
+
+#[test]
+fn invalid_host_keeps_template() {
+    assert_eq!(dump("<a><template shadowrootmode=open>a</template></a>").as_slice(),
+               "| <a>\n\
+                |   <template>\n\
+                |     shadowrootmode=\"open\"\n\
+                |     content\n\
+                |       \"a\"\n");
+}
+
+#[test]
+fn second_template_on_same_host() {
+    assert_eq!(dump("<div><template shadowrootmode=open>a</template>\
+                     <template shadowrootmode=closed>b</template></div>").as_slice(),
+               "| <div>\n\
+                |   #shadow-root (open)\n\
+                |     \"a\"\n\
+                |   <template>\n\
+                |     shadowrootmode=\"closed\"\n\
+                |     content\n\
+                |       \"b\"\n");
+}
+
+#[test]
+fn delegates_focus_and_clonable() {
+    let data = "<div><template shadowrootmode=\"open\" shadowrootdelegatesfocus=\"\" \
+                shadowrootclonable=\"\">a</template></div>";
+    assert_eq!(dump(data).as_slice(),
+               "| <div>\n\
+                |   #shadow-root (open, delegatesfocus, clonable)\n\
+                |     \"a\"\n");
+    round_trip(data);
+
+    let document = parse(data);
+    let host = document.first_child(document.root()).unwrap();
+    let init = document.shadow_root_init(host).unwrap();
+    assert!(init.delegates_focus);
+    assert!(init.clonable);
+}
+
+#[test]
+fn clone_copies_clonable_shadow_root() {
+    let mut document = parse("<div><template shadowrootmode=open shadowrootclonable>a</template>\
+                              b</div>");
+    let host = document.first_child(document.root()).unwrap();
+    let clone = document.clone_node(host, false);
+    assert!(document.first_child(clone).is_none());
+    let shadow_root = document.shadow_root(clone).unwrap();
+    assert!(shadow_root != document.shadow_root(host).unwrap());
+    assert_eq!(document.text_content(shadow_root).as_slice(), "a");
+    assert!(document.shadow_root_init(clone).unwrap().clonable);
+
+    let mut document = parse("<div><template shadowrootmode=open>a</template>b</div>");
+    let host = document.first_child(document.root()).unwrap();
+    let clone = document.clone_node(host, true);
+    assert_eq!(document.text_content(clone).as_slice(), "b");
+    assert!(document.shadow_root(clone).is_none());
+}
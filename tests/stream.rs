@@ -0,0 +1,102 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the elements streaming selectors report, as "name:text content", whether the input
+// comes in one chunk or byte by byte.
+
+extern crate hubbub;
+
+use hubbub::hubbub::Tag;
+use hubbub::stream::{StreamingSelectors, SelectorHandler};
+
+struct Recorder {
+    matches: Vec<String>,
+}
+
+impl SelectorHandler for Recorder {
+    fn element_matched(&mut self, tag: &Tag, text: &str) {
+        self.matches.push(format!("{}:{}", tag.name, text));
+    }
+}
+
+// The elements matched, and how many node slots the parse needed.
+fn parse(data: &str, selectors: &str, chunk_size: uint) -> (Vec<String>, uint) {
+    let mut recorder = Recorder { matches: Vec::new() };
+    let arena_size = {
+        let mut streaming = StreamingSelectors::new();
+        streaming.add(selectors, &mut recorder);
+        streaming.parse("UTF-8", |parser| {
+            for chunk in data.as_bytes().chunks(chunk_size) {
+                parser.parse_chunk(chunk);
+            }
+            parser.completed();
+        });
+        streaming.arena_size()
+    };
+    (recorder.matches, arena_size)
+}
+
+fn matches(data: &str, selectors: &str, chunk_size: uint) -> Vec<String> {
+    let (matches, _) = parse(data, selectors, chunk_size);
+    matches
+}
+
+fn check(data: &str, selectors: &str, expected: &[&str]) {
+    let expected: Vec<String> = expected.iter().map(|name| name.to_string()).collect();
+    assert_eq!(matches(data, selectors, data.len()), expected);
+    assert_eq!(matches(data, selectors, 1), expected);
+}
+
+#[test]
+fn elements_match_as_they_finish() {
+    check("<ul><li>a<li class=x>b</ul><p>c</p>", "li", &["li:a", "li:b"]);
+    check("<div>a<div>b</div>c</div>", "div", &["div:b", "div:abc"]);
+}
+
+#[test]
+fn combinators_see_ancestors_and_earlier_siblings() {
+    check("<ul><li>a<li class=x>b</ul><p>c</p>", "ul > .x", &["li:b"]);
+    check("<ul><li>a<li class=x>b</ul><p>c</p>", "li + li", &["li:b"]);
+    check("<ul><li>a<li class=x>b</ul><p>c</p>", "ul ~ p", &["p:c"]);
+    check("<div><p>a</p><span>b</span></div>", "p + span", &["span:b"]);
+}
+
+#[test]
+fn content_of_the_finished_element() {
+    check("<p>a<b>b</b></p><p>c</p>", "p:has(b)", &["p:ab"]);
+    check("<p>a</p><p></p>", "p:empty", &["p:"]);
+    check("<p>a<b>b</b></p><p>c</p>", "p:not(:has(b))", &["p:c"]);
+}
+
+#[test]
+fn content_kept_for_selectors_on_descendants() {
+    check("<div><p><b>x</b></p></div>", "div:has(b)", &["div:x"]);
+    check("<div><p><b>x</b></p></div>", "div:not(:has(b))", &[]);
+    check("<div><p><i>x</i></p></div>", "div:not(:has(b))", &["div:x"]);
+    check("<div><p>x</p><span>y</span></div>", "p:empty + span", &[]);
+    check("<div><p></p><span>y</span></div>", "p:empty + span", &["span:y"]);
+    check("<p><b>x</b></p><div>y</div>", "p:has(b) + div", &["div:y"]);
+}
+
+#[test]
+fn memory_stays_flat() {
+    let item = "<div class=item><p>a <b>b</b> <i>c</i> <a href=d>e</a></p><!--f--></div>\n";
+    let selectors = "title, h1";
+    let size = |count: uint| {
+        let mut data = "<title>t</title>".to_string();
+        for _ in range(0, count) {
+            data.push_str(item);
+        }
+        data.push_str("<h1>h</h1>");
+        let (matches, arena_size) = parse(data.as_slice(), selectors, 100);
+        assert_eq!(matches, vec!("title:t".to_string(), "h1:h".to_string()));
+        arena_size
+    };
+    assert_eq!(size(100), size(1000));
+}
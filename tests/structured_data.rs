@@ -0,0 +1,98 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the extraction of JSON-LD, Microdata and RDFa Lite.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::structured_data::{extract, StructuredData, Item, PropertyValue};
+use hubbub::structured_data::{TextValue, UrlValue, ItemValue};
+
+fn extract_from(data: &str, document_url: &str) -> StructuredData {
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    extract(&document, document_url)
+}
+
+fn item(types: &[&str], id: Option<&str>, vocab: Option<&str>,
+        properties: Vec<(&str, PropertyValue)>) -> Item {
+    Item {
+        types: types.iter().map(|name| name.to_string()).collect(),
+        id: id.map(|id| id.to_string()),
+        vocab: vocab.map(|vocab| vocab.to_string()),
+        properties: properties.move_iter().map(|(name, value)| {
+            (name.to_string(), value)
+        }).collect(),
+    }
+}
+
+fn text(value: &str) -> PropertyValue {
+    TextValue(value.to_string())
+}
+
+fn url(value: &str) -> PropertyValue {
+    UrlValue(value.to_string())
+}
+
+#[test]
+fn json_ld() {
+    let data = extract_from("<script type=\" application/LD+json\">{\"a\":1}</script>\
+                             <script>{}</script>", "http://example.com/");
+    assert_eq!(data.json_ld, vec!("{\"a\":1}".to_string()));
+}
+
+#[test]
+fn microdata() {
+    let data = extract_from("<div itemscope itemtype=\"http://schema.org/Person\" \
+                             itemid=\"/people/1\"><span itemprop=name>Ann</span><a \
+                             itemprop=url href=\"/ann\">x</a><div itemprop=address \
+                             itemscope><span itemprop=city>Paris</span></div><meta \
+                             itemprop=age content=30></div><p itemscope itemid=\"/untyped\">\
+                             <span itemprop=\"a b\">v</span></p>",
+                            "http://example.com/dir/page");
+    let address = item(&[], None, None, vec!(("city", text("Paris"))));
+    assert_eq!(data.microdata, vec!(
+        item(&["http://schema.org/Person"], Some("http://example.com/people/1"), None,
+             vec!(("name", text("Ann")), ("url", url("http://example.com/ann")),
+                  ("address", ItemValue(address)), ("age", text("30")))),
+        item(&[], None, None, vec!(("a", text("v")), ("b", text("v"))))));
+}
+
+#[test]
+fn microdata_itemref() {
+    let data = extract_from("<div itemscope itemref=\"extra\"><span itemprop=a>1</span></div>\
+                             <p id=extra itemprop=b>2</p>", "http://example.com/");
+    assert_eq!(data.microdata, vec!(item(&[], None, None, vec!(("a", text("1")),
+                                                               ("b", text("2"))))));
+}
+
+#[test]
+fn unresolvable_urls_are_empty() {
+    let data = extract_from("<div itemscope><a itemprop=u href=\"/x\">x</a><a itemprop=v \
+                             href=\"http://a.example/\">y</a></div>", "");
+    assert_eq!(data.microdata, vec!(item(&[], None, None,
+                                         vec!(("u", text("")),
+                                              ("v", url("http://a.example/"))))));
+}
+
+#[test]
+fn rdfa() {
+    let data = extract_from("<div vocab=\"http://schema.org/\" typeof=Person \
+                             prefix=\"og: http://ogp.me/ns#\"><span property=name>Bob</span>\
+                             <a property=\"og:url\" href=\"/bob\">b</a><div property=knows \
+                             typeof=Person><span property=name>Carl</span></div></div>\
+                             <span property=\"og:title\">outside</span>",
+                            "http://example.com/");
+    let vocab = Some("http://schema.org/");
+    let carl = item(&["Person"], None, vocab, vec!(("name", text("Carl"))));
+    assert_eq!(data.rdfa, vec!(
+        item(&["Person"], None, vocab,
+             vec!(("name", text("Bob")), ("http://ogp.me/ns#url", url("http://example.com/bob")),
+                  ("knows", ItemValue(carl))))));
+}
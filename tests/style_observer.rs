@@ -0,0 +1,102 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the stylesheets the parser reports to a style observer. Each is written as its order,
+// its source and whichever of its media, title and alternate flag are set.
+
+extern crate hubbub;
+
+use std::cell::RefCell;
+use hubbub::dom;
+use hubbub::dom::Document;
+use hubbub::hubbub::{Parser, StyleObserver, Stylesheet, InlineStylesheet, ExternalStylesheet};
+
+struct StylesheetRecorder {
+    stylesheets: Vec<String>,
+}
+
+impl StyleObserver for StylesheetRecorder {
+    fn stylesheet_found(&mut self, stylesheet: Stylesheet) {
+        let mut description = match stylesheet.source {
+            InlineStylesheet(text) => format!("{} inline {}", stylesheet.order, text),
+            ExternalStylesheet(href) => format!("{} external {}", stylesheet.order, href),
+        };
+        match stylesheet.media {
+            Some(media) => description.push_str(format!(" media={}", media).as_slice()),
+            None => {}
+        }
+        match stylesheet.title {
+            Some(title) => description.push_str(format!(" title={}", title).as_slice()),
+            None => {}
+        }
+        if stylesheet.alternate {
+            description.push_str(" alternate");
+        }
+        self.stylesheets.push(description);
+    }
+}
+
+fn stylesheets(data: &str) -> Vec<String> {
+    let document = RefCell::new(Document::new());
+    let mut recorder = StylesheetRecorder {
+        stylesheets: Vec::new(),
+    };
+    dom::with_tree_handler(&document, |tree_handler| {
+        let root = document.borrow().root().to_node_ptr();
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_tree_handler(tree_handler);
+        parser.set_document_node(root);
+        parser.set_style_observer(&mut recorder);
+        parser.parse_chunk(data.as_bytes());
+        parser.completed();
+    });
+    recorder.stylesheets
+}
+
+fn check(data: &str, expected: &[&str]) {
+    let expected: Vec<String> = expected.iter().map(|stylesheet| stylesheet.to_string()).collect();
+    assert_eq!(stylesheets(data), expected);
+}
+
+#[test]
+fn inline_and_external() {
+    check("<style>a { color: red }</style><link rel=stylesheet href=b.css>",
+          &["0 inline a { color: red }", "1 external b.css"]);
+}
+
+// Links that aren't stylesheets, or have no href, aren't reported.
+#[test]
+fn links_without_stylesheets() {
+    check("<link rel=icon href=a.png><link rel=stylesheet><link rel=\"Stylesheet\" href=b.css>",
+          &["0 external b.css"]);
+}
+
+#[test]
+fn media_and_title() {
+    check("<style media=print title=a>b {}</style>\
+           <link rel=stylesheet href=c.css media=\"screen and (color)\" title=d>",
+          &["0 inline b {} media=print title=a",
+            "1 external c.css media=screen and (color) title=d"]);
+}
+
+#[test]
+fn alternate_stylesheets() {
+    check("<link rel=\"alternate stylesheet\" href=a.css title=a>\
+           <link rel=\" stylesheet\tALTERNATE \" href=b.css title=b>\
+           <link rel=alternate href=c.xml>",
+          &["0 external a.css title=a alternate", "1 external b.css title=b alternate"]);
+}
+
+#[test]
+fn document_order() {
+    check("<link rel=stylesheet href=a.css><style>b {}</style><link rel=stylesheet href=c.css>\
+           <body><p><style>d {}</style><link rel=stylesheet href=e.css></p>",
+          &["0 external a.css", "1 inline b {}", "2 external c.css", "3 inline d {}",
+            "4 external e.css"]);
+}
@@ -0,0 +1,86 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks table extraction, by the text of each slot of the grid.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::tables;
+use hubbub::tables::Table;
+
+fn parse_tables(data: &str) -> Vec<Table> {
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    tables::tables(&document)
+}
+
+fn rows(data: &str) -> Vec<Vec<String>> {
+    parse_tables(data).get(0).to_rows()
+}
+
+// The rows of a grid, with the texts of the slots of each separated by commas.
+fn grid(rows: &[&str]) -> Vec<Vec<String>> {
+    rows.iter().map(|row| row.split(',').map(|text| text.to_string()).collect()).collect()
+}
+
+#[test]
+fn spans_and_row_groups() {
+    let tables = parse_tables("<table><caption> Cap  tion </caption><thead><tr><th>A<th>B<th>C\
+                               </thead><tbody><tr><td rowspan=2>1<td colspan=2>2<tr><td>3\
+                               <td>4</tbody><tfoot><tr><td>f</tfoot></table>");
+    let table = tables.get(0);
+    assert_eq!(table.caption, Some("Cap tion".to_string()));
+    assert_eq!((table.width, table.height, table.header_rows), (3, 4, 1));
+    assert_eq!(table.to_rows(), grid(&["A,B,C", "1,2,2", "1,3,4", "f,,"]));
+    let cell = table.cell_at(0, 2).unwrap();
+    assert_eq!((cell.x, cell.y, cell.width, cell.height, cell.header), (0, 1, 1, 2, false));
+    assert_eq!(table.to_csv(), "A,B,C\r\n1,2,2\r\n1,3,4\r\nf,,\r\n".to_string());
+}
+
+#[test]
+fn footers_go_last() {
+    assert_eq!(rows("<table><tfoot><tr><td>f</tfoot><tr><td>a</table>"),
+               grid(&["a", "f"]));
+}
+
+#[test]
+fn rowspans_stop_at_the_row_group() {
+    assert_eq!(rows("<table><tbody><tr><td rowspan=5>a<td>b</tbody><tbody><tr><td>c</table>"),
+               grid(&["a,b", "c,"]));
+    assert_eq!(rows("<table><tr><td rowspan=0>a<td>b<tr><td>c</table>"),
+               grid(&["a,b", "a,c"]));
+}
+
+#[test]
+fn span_attributes() {
+    assert_eq!(rows("<table><tr><td colspan=\" +2x\">a<td colspan=0>b<td colspan=-1>c</table>"),
+               grid(&["a,a,b,c"]));
+}
+
+#[test]
+fn nested_tables() {
+    let tables = parse_tables("<table><tr><td>x<table><tr><td>inner</table>y</table>");
+    assert_eq!(tables.len(), 2);
+    assert_eq!(tables.get(0).to_rows(), grid(&["xy"]));
+    assert_eq!(tables.get(1).to_rows(), grid(&["inner"]));
+}
+
+#[test]
+fn headers() {
+    let tables = parse_tables("<table><tr><th>a<th>b</table><table><tr><th>a<th>b<tr><td>c\
+                               <td>d</table>");
+    assert_eq!(tables.get(0).header_rows, 0);
+    assert_eq!(tables.get(1).header_rows, 1);
+}
+
+#[test]
+fn csv_quoting() {
+    let tables = parse_tables("<table><tr><td>a,\"b\"<td>c</table>");
+    assert_eq!(tables.get(0).to_csv(), "\"a,\"\"b\"\"\",c\r\n".to_string());
+}
@@ -0,0 +1,55 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks the plain-text rendering of parsed documents.
+
+extern crate hubbub;
+
+use hubbub::dom;
+use hubbub::text;
+
+fn render(data: &str, width: Option<uint>) -> String {
+    let document = dom::parse(data.as_bytes(), "UTF-8");
+    text::render(&document, width)
+}
+
+#[test]
+fn paragraphs() {
+    assert_eq!(render("<title>T</title><p>Hello   <b>world</b></p><p>Next\nline</p>", None),
+               "Hello world\n\nNext line\n".to_string());
+    assert_eq!(render("<p>a<br>b<br><br>c</p>", None), "a\nb\n\nc\n".to_string());
+    assert_eq!(render("a<script>b</script><style>c</style><template>d</template>e", None),
+               "ae\n".to_string());
+}
+
+#[test]
+fn lists() {
+    assert_eq!(render("<ul><li>a<li>b<ol start=3><li>c<li>d</ol></ul>", None),
+               "* a\n* b\n  3. c\n  4. d\n".to_string());
+}
+
+#[test]
+fn tables() {
+    assert_eq!(render("<table><tr><th>A<th>B<tr><td>1<td>2</table>", None),
+               "A\tB\n1\t2\n".to_string());
+}
+
+#[test]
+fn preformatted() {
+    assert_eq!(render("<p>x</p><pre>a\n  b</pre>", None), "x\n\na\n  b\n".to_string());
+}
+
+#[test]
+fn wrapping() {
+    assert_eq!(render("<p>one two three four</p>", Some(9)),
+               "one two\nthree\nfour\n".to_string());
+    assert_eq!(render("<ul><li>aaa bbb ccc</ul>", Some(9)), "* aaa bbb\n  ccc\n".to_string());
+    assert_eq!(render("<pre>one two three four</pre>", Some(9)),
+               "one two three four\n".to_string());
+}
@@ -0,0 +1,282 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Runs the vendored html5lib tokenizer tests through the parser's token handler.
+
+extern crate hubbub;
+extern crate serialize;
+
+use std::char;
+use std::collections::{HashSet, TreeMap};
+use std::io::File;
+use std::io::fs;
+use std::num;
+use serialize::json;
+use serialize::json::{Json, ToJson};
+use hubbub::hubbub::{Parser, TokenHandler, Token, DoctypeToken, StartTagToken, EndTagToken};
+use hubbub::hubbub::{CommentToken, CharacterToken, EofToken};
+use hubbub::hubbub::{ContentModel, PCData, RCData, CData, PlainText};
+
+fn html5lib_tests_dir() -> Path {
+    Path::new(file!()).dir_path().join("html5lib-tests")
+}
+
+fn load_skips() -> HashSet<String> {
+    let path = html5lib_tests_dir().join("tokenizer-skips.txt");
+    let contents = File::open(&path).read_to_string().unwrap();
+    contents.as_slice().lines().map(|line| line.trim()).filter(|line| {
+        !line.is_empty() && !line.starts_with("#")
+    }).map(|line| line.to_string()).collect()
+}
+
+// Maps an html5lib initial state onto the content model that puts Hubbub's tokenizer in it.
+fn content_model_for_state(state: &str) -> Option<ContentModel> {
+    match state {
+        "Data state" => Some(PCData),
+        "RCDATA state" => Some(RCData),
+        "RAWTEXT state" | "Script data state" => Some(CData),
+        "PLAINTEXT state" => Some(PlainText),
+        _ => None
+    }
+}
+
+fn token_to_json(token: Token) -> Option<Json> {
+    let json = match token {
+        DoctypeToken(doctype) => {
+            let public_id = doctype.public_id.map_or(json::Null, |id| id.to_json());
+            let system_id = doctype.system_id.map_or(json::Null, |id| id.to_json());
+            vec!("DOCTYPE".to_string().to_json(), doctype.name.to_json(), public_id, system_id,
+                 (!doctype.force_quirks).to_json())
+        }
+        StartTagToken(tag) => {
+            let mut attributes = TreeMap::new();
+            for attribute in tag.attributes.move_iter() {
+                // Only the first of several attributes with the same name is kept.
+                if !attributes.contains_key(&attribute.name) {
+                    attributes.insert(attribute.name, attribute.value.to_json());
+                }
+            }
+            let mut json = vec!("StartTag".to_string().to_json(), tag.name.to_json(),
+                                attributes.to_json());
+            if tag.self_closing {
+                json.push(true.to_json());
+            }
+            json
+        }
+        EndTagToken(tag) => vec!("EndTag".to_string().to_json(), tag.name.to_json()),
+        CommentToken(data) => vec!("Comment".to_string().to_json(), data.to_json()),
+        CharacterToken(data) => vec!("Character".to_string().to_json(), data.to_json()),
+        EofToken => return None,
+    };
+    Some(json.to_json())
+}
+
+// Drops parse errors and merges adjacent character tokens, since tokenizers are free to split
+// character data however they like.
+fn normalize(tokens: Vec<Json>) -> Vec<Json> {
+    let mut result: Vec<Json> = Vec::new();
+    for token in tokens.move_iter() {
+        if token.as_string() == Some("ParseError") {
+            continue
+        }
+        let characters = character_data(&token);
+        let previous = result.last().and_then(|last| character_data(last));
+        match (characters, previous) {
+            (Some(data), Some(previous)) => {
+                let merged = format!("{}{}", previous, data);
+                result.pop();
+                result.push(vec!("Character".to_string().to_json(), merged.to_json()).to_json());
+            }
+            _ => result.push(token),
+        }
+    }
+    result
+}
+
+fn character_data(token: &Json) -> Option<String> {
+    let list = match token.as_list() {
+        Some(list) => list,
+        None => return None
+    };
+    if list.len() == 2 && list.get(0).as_string() == Some("Character") {
+        list.get(1).as_string().map(|data| data.to_string())
+    } else {
+        None
+    }
+}
+
+// Undoes the extra level of escaping in "doubleEscaped" tests. Fails for escapes that denote
+// lone surrogates, which cannot be represented in a Rust string.
+fn unescape(input: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = input;
+    loop {
+        match rest.find_str("\\u") {
+            Some(index) => {
+                result.push_str(rest.slice_to(index));
+                let code = match num::from_str_radix::<u32>(rest.slice(index + 2, index + 6), 16) {
+                    Some(code) => code,
+                    None => return None
+                };
+                match char::from_u32(code) {
+                    Some(c) => result.push_char(c),
+                    None => return None
+                }
+                rest = rest.slice_from(index + 6);
+            }
+            None => {
+                result.push_str(rest);
+                return Some(result)
+            }
+        }
+    }
+}
+
+fn unescape_json(json: &Json) -> Option<Json> {
+    match json.as_string() {
+        Some(string) => return unescape(string).map(|string| string.to_json()),
+        None => {}
+    }
+    match json.as_list() {
+        Some(list) => {
+            let mut result = Vec::new();
+            for item in list.iter() {
+                match unescape_json(item) {
+                    Some(item) => result.push(item),
+                    None => return None
+                }
+            }
+            return Some(result.to_json())
+        }
+        None => {}
+    }
+    match json.as_object() {
+        Some(object) => {
+            let mut result = TreeMap::new();
+            for (key, value) in object.iter() {
+                match (unescape(key.as_slice()), unescape_json(value)) {
+                    (Some(key), Some(value)) => { result.insert(key, value); }
+                    _ => return None
+                }
+            }
+            Some(result.to_json())
+        }
+        None => Some(json.clone())
+    }
+}
+
+fn tokenize(input: &str, content_model: ContentModel, last_start_tag: Option<&str>)
+            -> Vec<Json> {
+    let mut tokens = Vec::new();
+    {
+        let mut token_handler = TokenHandler {
+            process_token: |token| {
+                match token_to_json(token) {
+                    Some(json) => tokens.push(json),
+                    None => {}
+                }
+            }
+        };
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_token_handler(&mut token_handler);
+
+        // The tokenizer only recognises an end tag as appropriate in RCDATA and RAWTEXT once it
+        // has seen the matching start tag, so feed one before switching content models.
+        match last_start_tag {
+            Some(name) => parser.parse_chunk(format!("<{}>", name).as_bytes()),
+            None => {}
+        }
+
+        parser.set_content_model(content_model);
+
+        parser.parse_chunk(input.as_bytes());
+        parser.completed();
+    }
+    if last_start_tag.is_some() {
+        tokens.remove(0);
+    }
+    normalize(tokens)
+}
+
+#[test]
+fn tokenizer() {
+    let skips = load_skips();
+    let mut paths = fs::readdir(&html5lib_tests_dir().join("tokenizer")).unwrap();
+    paths.retain(|path| path.extension_str() == Some("test"));
+    paths.sort_by(|a, b| a.filename_str().cmp(&b.filename_str()));
+
+    let mut failures = Vec::new();
+    for path in paths.iter() {
+        let filename = path.filename_str().unwrap();
+        let contents = File::open(path).read_to_string().unwrap();
+        let json = json::from_str(contents.as_slice()).unwrap();
+        let tests = json.find(&"tests".to_string()).and_then(|tests| tests.as_list()).unwrap();
+        let (mut passed, mut failed, mut skipped) = (0u, 0u, 0u);
+
+        for (index, test) in tests.iter().enumerate() {
+            let description = test.find(&"description".to_string())
+                                  .and_then(|d| d.as_string()).unwrap_or("");
+            let double_escaped = test.find(&"doubleEscaped".to_string())
+                                     .and_then(|d| d.as_boolean()).unwrap_or(false);
+            let mut input = test.find(&"input".to_string()).unwrap().clone();
+            let mut output = test.find(&"output".to_string()).unwrap().clone();
+            if double_escaped {
+                match (unescape_json(&input), unescape_json(&output)) {
+                    (Some(i), Some(o)) => { input = i; output = o; }
+                    _ => {
+                        skipped += 1;
+                        continue
+                    }
+                }
+            }
+            let expected = normalize(output.as_list().unwrap().clone());
+            let last_start_tag = test.find(&"lastStartTag".to_string())
+                                     .and_then(|tag| tag.as_string());
+            let states = match test.find(&"initialStates".to_string()) {
+                Some(states) => states.as_list().unwrap().iter().map(|state| {
+                    state.as_string().unwrap().to_string()
+                }).collect(),
+                None => vec!("Data state".to_string()),
+            };
+
+            if skips.contains(&format!("{}:{}", filename, index)) {
+                skipped += 1;
+                continue
+            }
+
+            for state in states.iter() {
+                let content_model = match content_model_for_state(state.as_slice()) {
+                    Some(content_model) => content_model,
+                    None => {
+                        skipped += 1;
+                        continue
+                    }
+                };
+                let actual = tokenize(input.as_string().unwrap(), content_model, last_start_tag);
+                if actual == expected {
+                    passed += 1;
+                } else {
+                    failed += 1;
+                    failures.push(format!("{}:{} {} ({}):\nexpected: {}\ngot: {}",
+                                          filename, index, description, state,
+                                          expected.to_json(), actual.to_json()));
+                }
+            }
+        }
+
+        println!("{}: {}/{} passed, {} skipped", filename, passed, passed + failed, skipped);
+    }
+
+    for failure in failures.iter() {
+        println!("FAIL {}", failure);
+    }
+    if !failures.is_empty() {
+        fail!("{} tokenizer tests failed", failures.len());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Runs the vendored html5lib tree construction tests, and the local ones in the same format,
+// through the parser, building the tree with the arena sink and comparing its dump with the
+// expected one.
+
+extern crate hubbub;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::File;
+use std::io::fs;
+use hubbub::dom;
+use hubbub::dom::Document;
+use hubbub::dump;
+use hubbub::hubbub::{Parser, Tag, HtmlNs, SvgNs, MathMlNs};
+
+struct Section {
+    header: String,
+    lines: Vec<String>,
+}
+
+struct TestCase {
+    data: String,
+    document: String,
+    fragment_context: Option<String>,
+    scripting: Option<bool>,
+}
+
+fn html5lib_tests_dir() -> Path {
+    Path::new(file!()).dir_path().join("html5lib-tests")
+}
+
+// Cases for behaviour the upstream suite doesn't cover, kept apart from the vendored files.
+fn local_tests_dir() -> Path {
+    Path::new(file!()).dir_path().join("tree-construction")
+}
+
+fn parse_test_file(contents: &str) -> Vec<TestCase> {
+    let mut tests = Vec::new();
+    let mut sections: Vec<Section> = Vec::new();
+    for line in contents.lines() {
+        if line == "#data" && !sections.is_empty() {
+            tests.push(make_test_case(sections));
+            sections = Vec::new();
+        }
+        if line.starts_with("#") {
+            sections.push(Section {
+                header: line.to_string(),
+                lines: Vec::new(),
+            });
+        } else {
+            match sections.mut_last() {
+                Some(section) => section.lines.push(line.to_string()),
+                None => {}
+            }
+        }
+    }
+    if !sections.is_empty() {
+        tests.push(make_test_case(sections));
+    }
+    tests
+}
+
+fn make_test_case(sections: Vec<Section>) -> TestCase {
+    let mut test = TestCase {
+        data: String::new(),
+        document: String::new(),
+        fragment_context: None,
+        scripting: None,
+    };
+    for section in sections.move_iter() {
+        let mut lines = section.lines;
+        match section.header.as_slice() {
+            "#data" => test.data = lines.connect("\n"),
+            "#document-fragment" => test.fragment_context = Some(lines.connect("\n")),
+            "#script-on" => test.scripting = Some(true),
+            "#script-off" => test.scripting = Some(false),
+            "#document" => {
+                // The blank line separating this test from the next one isn't part of the tree.
+                while lines.last().map_or(false, |line| line.is_empty()) {
+                    lines.pop();
+                }
+                for line in lines.iter() {
+                    test.document.push_str(line.as_slice());
+                    test.document.push_char('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+    test
+}
+
+fn load_skips() -> HashSet<String> {
+    let path = html5lib_tests_dir().join("tree-construction-skips.txt");
+    let contents = File::open(&path).read_to_string().unwrap();
+    contents.as_slice().lines().map(|line| line.trim()).filter(|line| {
+        !line.is_empty() && !line.starts_with("#")
+    }).map(|line| line.to_string()).collect()
+}
+
+fn parse_document(data: &str, scripting: bool) -> String {
+    let document = RefCell::new(Document::new());
+    dom::with_tree_handler(&document, |tree_handler| {
+        let root = document.borrow().root().to_node_ptr();
+        let mut parser = Parser::new("UTF-8", true);
+        parser.set_tree_handler(tree_handler);
+        parser.set_document_node(root);
+        parser.enable_scripting(scripting);
+        parser.parse_chunk(data.as_bytes());
+        parser.completed();
+    });
+    let document = document.unwrap();
+    dump::to_string(&document, document.root())
+}
+
+// Fragment contexts are written as "svg path" or "math mi" for foreign elements.
+fn parse_fragment(data: &str, context: &str) -> String {
+    let (ns, name) = if context.starts_with("svg ") {
+        (SvgNs, context.slice_from(4))
+    } else if context.starts_with("math ") {
+        (MathMlNs, context.slice_from(5))
+    } else {
+        (HtmlNs, context)
+    };
+    let context = Tag {
+        ns: ns,
+        name: name.to_string(),
+        attributes: Vec::new(),
+        self_closing: false,
+    };
+    let document = dom::parse_fragment(&context, data.as_bytes());
+    dump::to_string(&document, document.root())
+}
+
+#[test]
+fn tree_construction() {
+    let skips = load_skips();
+    let mut paths = Vec::new();
+    for dir in [html5lib_tests_dir().join("tree-construction"), local_tests_dir()].iter() {
+        let mut dir_paths = fs::readdir(dir).unwrap();
+        dir_paths.retain(|path| path.extension_str() == Some("dat"));
+        dir_paths.sort_by(|a, b| a.filename_str().cmp(&b.filename_str()));
+        paths.push_all_move(dir_paths);
+    }
+
+    let mut failures = Vec::new();
+    for path in paths.iter() {
+        let filename = path.filename_str().unwrap();
+        let contents = File::open(path).read_to_string().unwrap();
+        let (mut passed, mut failed, mut skipped) = (0u, 0u, 0u);
+
+        for (index, test) in parse_test_file(contents.as_slice()).iter().enumerate() {
+            // Fragments are always parsed with scripting disabled.
+            if skips.contains(&format!("{}:{}", filename, index)) ||
+                    (test.fragment_context.is_some() && test.scripting == Some(true)) {
+                skipped += 1;
+                continue
+            }
+
+            // Tests that don't depend on the scripting flag run with it both on and off.
+            for &scripting in [false, true].iter() {
+                if test.scripting.map_or(false, |required| required != scripting) ||
+                        (test.fragment_context.is_some() && scripting) {
+                    continue
+                }
+                let actual = match test.fragment_context {
+                    Some(ref context) => parse_fragment(test.data.as_slice(), context.as_slice()),
+                    None => parse_document(test.data.as_slice(), scripting),
+                };
+                if actual == test.document {
+                    passed += 1;
+                } else {
+                    failed += 1;
+                    failures.push(format!("{}:{} (scripting {}):\n{}\nexpected:\n{}got:\n{}",
+                                          filename, index, if scripting { "on" } else { "off" },
+                                          test.data, test.document, actual));
+                }
+            }
+        }
+
+        println!("{}: {}/{} passed, {} skipped", filename, passed, passed + failed, skipped);
+    }
+
+    for failure in failures.iter() {
+        println!("FAIL {}", failure);
+    }
+    if !failures.is_empty() {
+        fail!("{} tree construction tests failed", failures.len());
+    }
+}